@@ -1,5 +1,7 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use itertools::Itertools;
+use rng::Rng;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
@@ -17,6 +19,11 @@ enum Input {
     Puzzle,
 }
 
+enum Mode {
+    Execute,
+    DryRun,
+}
+
 #[derive(Debug)]
 struct Instruction {
     number: usize,
@@ -37,7 +44,40 @@ impl FromStr for Instruction {
     }
 }
 
-#[derive(Debug)]
+impl Instruction {
+    // Check that the instruction can be carried out on stacks with the given
+    // heights. `height` is called with zero-based stack indices.
+    fn validate(
+        &self,
+        num_stacks: usize,
+        height: impl Fn(usize) -> usize,
+    ) -> Result<(), MoveError> {
+        for stack in [self.from, self.to] {
+            if stack == 0 || stack > num_stacks {
+                return Err(MoveError::NoSuchStack(stack));
+            }
+        }
+
+        let available = height(self.from - 1);
+        if available < self.number {
+            return Err(MoveError::NotEnoughCrates {
+                stack: self.from,
+                available,
+                requested: self.number,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "move {} from {} to {}", self.number, self.from, self.to)
+    }
+}
+
+#[derive(Debug, PartialEq)]
 struct Stack {
     crates: Vec<char>,
 }
@@ -47,57 +87,395 @@ impl Stack {
         Stack { crates: Vec::new() }
     }
 
-    fn push(&mut self, c: char) -> () {
+    fn push(&mut self, c: char) {
         self.crates.push(c)
     }
 
-    fn pop(&mut self) -> char {
-        self.crates.pop().unwrap()
+    // Take the top `number` crates off in one go, keeping their order.
+    fn pop_multiple(&mut self, number: usize) -> Vec<char> {
+        let at = self.crates.len().saturating_sub(number);
+        self.crates.split_off(at)
     }
 
-    fn pop_multiple(&mut self, number: usize) -> Vec<char> {
-        let mut crates = Vec::new();
-        for _ in 0..number {
-            crates.push(self.pop());
+    fn push_multiple(&mut self, crates: Vec<char>, reversed: bool) {
+        if reversed {
+            self.crates.extend(crates.into_iter().rev());
+        } else {
+            self.crates.extend(crates);
         }
-        crates.reverse();
-        crates
     }
 
-    fn top(&self) -> char {
-        *self.crates.last().unwrap()
+    fn top(&self) -> Option<char> {
+        self.crates.last().copied()
+    }
+
+    fn height(&self) -> usize {
+        self.crates.len()
     }
 }
 
 #[derive(Debug)]
+enum DrawingError {
+    Empty,
+    NoLabels {
+        line: usize,
+    },
+    BadLabel {
+        line: usize,
+        expected: usize,
+        found: String,
+    },
+    BadCrate {
+        line: usize,
+        column: usize,
+    },
+    UnalignedCrate {
+        line: usize,
+        column: usize,
+    },
+    FloatingCrate {
+        line: usize,
+        stack: usize,
+    },
+}
+
+impl fmt::Display for DrawingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrawingError::Empty => write!(f, "stack drawing is empty"),
+            DrawingError::NoLabels { line } => write!(f, "line {}: no stack labels", line),
+            DrawingError::BadLabel {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected stack label {}, found '{}'",
+                line, expected, found
+            ),
+            DrawingError::BadCrate { line, column } => {
+                write!(f, "line {}, column {}: malformed crate", line, column)
+            }
+            DrawingError::UnalignedCrate { line, column } => write!(
+                f,
+                "line {}, column {}: crate is not below a stack label",
+                line, column
+            ),
+            DrawingError::FloatingCrate { line, stack } => write!(
+                f,
+                "line {}: crate in stack {} is not resting on another crate",
+                line, stack
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DrawingError {}
+
+#[derive(Debug, PartialEq)]
+enum MoveError {
+    NoSuchStack(usize),
+    NotEnoughCrates {
+        stack: usize,
+        available: usize,
+        requested: usize,
+    },
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::NoSuchStack(stack) => write!(f, "stack {} does not exist", stack),
+            MoveError::NotEnoughCrates {
+                stack,
+                available,
+                requested,
+            } => write!(
+                f,
+                "cannot move {} crates from stack {} holding {}",
+                requested, stack, available
+            ),
+        }
+    }
+}
+
+// `index` counts from 0, but messages number instructions from 1 like lines.
+#[derive(Debug, PartialEq)]
+struct ApplyError {
+    index: usize,
+    cause: MoveError,
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instruction {}: {}", self.index + 1, self.cause)
+    }
+}
+
+impl std::error::Error for ApplyError {}
+
+#[derive(Debug, PartialEq)]
 struct Cargo {
     stacks: Vec<Stack>,
 }
 
 impl Cargo {
-    fn move_crates_9000(&mut self, instr: Instruction) -> () {
-        for _ in 0..instr.number {
-            let crat = self.stacks[instr.from - 1].pop();
-            self.stacks[instr.to - 1].push(crat);
+    // Parse a stack drawing. The label row determines how many stacks there
+    // are and in which columns their crates are drawn, so crate lines may be
+    // right-trimmed or ragged and stack numbers may have multiple digits.
+    fn parse(stack_lines: &[String]) -> Result<Self, DrawingError> {
+        let (label_line, crate_lines) = stack_lines.split_last().ok_or(DrawingError::Empty)?;
+        let label_line_number = stack_lines.len();
+
+        // Column span (start, end) of every label, in stack order. Like crate
+        // columns below, these count characters rather than bytes.
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        let label_chars: Vec<char> = label_line.chars().collect();
+        let mut start = 0;
+        while start < label_chars.len() {
+            if label_chars[start].is_whitespace() {
+                start += 1;
+                continue;
+            }
+            let end = label_chars[start..]
+                .iter()
+                .position(|c| c.is_whitespace())
+                .map_or(label_chars.len(), |len| start + len);
+
+            let label: String = label_chars[start..end].iter().collect();
+            let expected = spans.len() + 1;
+            match label.parse::<usize>() {
+                Ok(number) if number == expected => spans.push((start, end)),
+                _ => {
+                    return Err(DrawingError::BadLabel {
+                        line: label_line_number,
+                        expected,
+                        found: label,
+                    })
+                }
+            }
+            start = end;
+        }
+        if spans.is_empty() {
+            return Err(DrawingError::NoLabels {
+                line: label_line_number,
+            });
+        }
+
+        let mut cargo = Cargo {
+            stacks: spans.iter().map(|_| Stack::new()).collect(),
+        };
+
+        for (index, line) in crate_lines.iter().enumerate().rev() {
+            let line_number = index + 1;
+            let level = crate_lines.len() - 1 - index;
+            let chars: Vec<char> = line.chars().collect();
+
+            let mut column = 0;
+            while column < chars.len() {
+                if chars[column].is_whitespace() {
+                    column += 1;
+                    continue;
+                }
+
+                let c = match chars.get(column..column + 3) {
+                    Some(&['[', c, ']']) if !c.is_whitespace() => c,
+                    _ => {
+                        return Err(DrawingError::BadCrate {
+                            line: line_number,
+                            column: column + 1,
+                        })
+                    }
+                };
+
+                let stack = spans
+                    .iter()
+                    .position(|&(start, end)| (start..end).contains(&(column + 1)))
+                    .ok_or(DrawingError::UnalignedCrate {
+                        line: line_number,
+                        column: column + 1,
+                    })?;
+                if cargo.stacks[stack].height() != level {
+                    return Err(DrawingError::FloatingCrate {
+                        line: line_number,
+                        stack: stack + 1,
+                    });
+                }
+                cargo.stacks[stack].push(c);
+
+                column += 3;
+            }
         }
+
+        Ok(cargo)
+    }
+
+    // Write the stacks back out in the puzzle's drawing format, including the
+    // label row and the trailing padding, so that `parse` can read it again.
+    fn to_drawing(&self) -> String {
+        let max_height = self
+            .stacks
+            .iter()
+            .map(|stack| stack.height())
+            .max()
+            .unwrap_or(0);
+
+        let mut lines: Vec<String> = (0..max_height)
+            .rev()
+            .map(|level| {
+                self.stacks
+                    .iter()
+                    .map(|stack| match stack.crates.get(level) {
+                        Some(c) => format!("[{}]", c),
+                        None => "   ".to_string(),
+                    })
+                    .join(" ")
+            })
+            .collect();
+
+        lines.push(
+            (1..=self.stacks.len())
+                .map(|label| format!("{:^3}", label))
+                .join(" "),
+        );
+
+        lines.join("\n")
     }
 
-    fn move_crates_9001(&mut self, instr: Instruction) -> () {
-        let crates = self.stacks[instr.from - 1].pop_multiple(instr.number);
-        for c in crates {
-            self.stacks[instr.to - 1].push(c);
+    // Carry out the instructions in order, stopping at the first one that
+    // cannot be executed. In dry-run mode only the stack heights are tracked,
+    // so a whole instruction list can be checked without touching the cargo.
+    fn apply(
+        &mut self,
+        instructions: &[Instruction],
+        crane: &mut dyn Crane,
+        mode: Mode,
+    ) -> Result<(), ApplyError> {
+        match mode {
+            Mode::Execute => {
+                for (index, instr) in instructions.iter().enumerate() {
+                    instr
+                        .validate(self.stacks.len(), |i| self.stacks[i].height())
+                        .map_err(|cause| ApplyError { index, cause })?;
+                    crane.move_crates(self, instr);
+                }
+            }
+            Mode::DryRun => {
+                let mut heights: Vec<usize> =
+                    self.stacks.iter().map(|stack| stack.height()).collect();
+                for (index, instr) in instructions.iter().enumerate() {
+                    instr
+                        .validate(heights.len(), |i| heights[i])
+                        .map_err(|cause| ApplyError { index, cause })?;
+                    heights[instr.from - 1] -= instr.number;
+                    heights[instr.to - 1] += instr.number;
+                }
+            }
         }
+
+        Ok(())
+    }
+
+    // Move the top `number` crates from one stack onto another as a single
+    // slice, optionally reversing them. Stacks are zero-based here.
+    fn move_slice(&mut self, from: usize, to: usize, number: usize, reversed: bool) {
+        let crates = self.stacks[from].pop_multiple(number);
+        self.stacks[to].push_multiple(crates, reversed);
     }
 
     fn top_crates(&self) -> String {
         self.stacks
             .iter()
-            .map(|stack| stack.top())
+            .filter_map(|stack| stack.top())
             .collect::<String>()
     }
 }
 
-fn solve(part: Part, input: Input) -> Result<String> {
+// A crane model. Instructions are validated before they reach the crane, so
+// `from` and `to` exist and `from` holds at least `number` crates.
+trait Crane {
+    fn move_crates(&mut self, cargo: &mut Cargo, instr: &Instruction);
+}
+
+// Moves crates one at a time, reversing their order. Since nothing else
+// happens in between, the whole move is done as one reversed slice. Moving
+// crates one at a time onto the stack they came from changes nothing.
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn move_crates(&mut self, cargo: &mut Cargo, instr: &Instruction) {
+        if instr.from == instr.to {
+            return;
+        }
+        cargo.move_slice(instr.from - 1, instr.to - 1, instr.number, true);
+    }
+}
+
+// Moves all crates at once, keeping their order.
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn move_crates(&mut self, cargo: &mut Cargo, instr: &Instruction) {
+        cargo.move_slice(instr.from - 1, instr.to - 1, instr.number, false);
+    }
+}
+
+// Lifts at most `capacity` crates at once, so large moves are split into
+// chunks taken from the top. Each chunk keeps its order.
+struct LimitedCrane {
+    capacity: usize,
+}
+
+impl Crane for LimitedCrane {
+    fn move_crates(&mut self, cargo: &mut Cargo, instr: &Instruction) {
+        let mut remaining = instr.number;
+        while remaining > 0 {
+            let chunk = remaining.min(self.capacity);
+            cargo.move_slice(instr.from - 1, instr.to - 1, chunk, false);
+            remaining -= chunk;
+        }
+    }
+}
+
+// Lifts all crates at once and flips every other crate over onto the one
+// below it: counting from the top of the lift, crates 1 and 2 swap places,
+// then 3 and 4, and so on. With an odd number of crates the bottom one stays
+// where it is.
+struct FlippingCrane;
+
+impl Crane for FlippingCrane {
+    fn move_crates(&mut self, cargo: &mut Cargo, instr: &Instruction) {
+        let mut crates = cargo.stacks[instr.from - 1].pop_multiple(instr.number);
+        for pair in crates.rchunks_exact_mut(2) {
+            pair.swap(0, 1);
+        }
+        cargo.stacks[instr.to - 1].push_multiple(crates, false);
+    }
+}
+
+// Select a crane from a command-line argument: `9000`, `9001`, `limited:<n>`
+// or `flipping`.
+fn parse_crane(arg: &str) -> Result<Box<dyn Crane>> {
+    let crane: Box<dyn Crane> = match arg.split_once(':') {
+        None if arg == "9000" => Box::new(CrateMover9000),
+        None if arg == "9001" => Box::new(CrateMover9001),
+        None if arg == "flipping" => Box::new(FlippingCrane),
+        Some(("limited", capacity)) => match capacity.parse() {
+            Ok(capacity) if capacity > 0 => Box::new(LimitedCrane { capacity }),
+            _ => return Err(anyhow!("invalid crane capacity '{}'", capacity)),
+        },
+        _ => return Err(anyhow!("unknown crane '{}'", arg)),
+    };
+    Ok(crane)
+}
+
+impl fmt::Display for Cargo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_drawing())
+    }
+}
+
+fn read_input(input: Input) -> Result<(Vec<String>, Vec<String>)> {
     let file = match input {
         Input::Test => File::open("test.txt")?,
         Input::Puzzle => File::open("input.txt")?,
@@ -107,34 +485,267 @@ fn solve(part: Part, input: Input) -> Result<String> {
     let lines: Vec<String> = reader.lines().map(|line| line.unwrap()).collect();
     let (stack_lines, instr_lines) = lines.split(|line| line.is_empty()).collect_tuple().unwrap();
 
-    // Contruct stacks.
-    let mut cargo = Cargo { stacks: Vec::new() };
-    let num_stacks = stack_lines[0].len() / 4 + 1;
-    for _ in 0..num_stacks {
-        cargo.stacks.push(Stack::new());
+    Ok((stack_lines.to_vec(), instr_lines.to_vec()))
+}
+
+fn round_trip(input: Input) -> Result<bool> {
+    let (stack_lines, instr_lines) = read_input(input)?;
+
+    let cargo = Cargo::parse(&stack_lines)?;
+    let instructions: Vec<Instruction> = instr_lines
+        .iter()
+        .map(|line| Instruction::from_str(line).unwrap())
+        .collect();
+
+    let drawing_ok = cargo.to_string() == stack_lines.join("\n");
+    let instr_ok = instructions
+        .iter()
+        .map(|instr| instr.to_string())
+        .eq(instr_lines.iter().cloned());
+    Ok(drawing_ok && instr_ok)
+}
+
+fn check_drawing_parser() -> Result<()> {
+    let lines = |text: &str| {
+        text.lines()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Right-trimmed and ragged lines parse the same as the padded drawing.
+    let (stack_lines, _) = read_input(Input::Test)?;
+    let padded = Cargo::parse(&stack_lines)?;
+    let trimmed = stack_lines
+        .iter()
+        .map(|line| line.trim_end().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(Cargo::parse(&trimmed)?, padded);
+    assert_eq!(
+        Cargo::parse(&lines("    [D]\n[N] [C]  \n[Z] [M] [P]\n 1   2   3"))?,
+        padded
+    );
+
+    // Multi-digit stack numbers survive a round trip.
+    let mut wide = Cargo {
+        stacks: (0..12).map(|_| Stack::new()).collect(),
+    };
+    for (i, stack) in wide.stacks.iter_mut().enumerate() {
+        for j in 0..i % 3 + 1 {
+            stack.push((b'A' + (i + j) as u8) as char);
+        }
     }
+    assert_eq!(Cargo::parse(&lines(&wide.to_drawing()))?, wide);
 
-    for line in stack_lines[0..stack_lines.len() - 1].iter().rev() {
-        for i in 0..num_stacks {
-            let chars = line.chars().collect::<Vec<char>>();
-            let c = chars[i * 4 + 1];
-            if !c.is_whitespace() {
-                cargo.stacks[i].push(c);
-            }
+    // Malformed drawings report where they went wrong.
+    let error = |text: &str| Cargo::parse(&lines(text)).unwrap_err().to_string();
+    assert_eq!(error(""), "stack drawing is empty");
+    assert_eq!(error("[A]\n   "), "line 2: no stack labels");
+    assert_eq!(
+        error("[A]\n 1   3"),
+        "line 2: expected stack label 2, found '3'"
+    );
+    assert_eq!(error("[A] [B\n 1   2"), "line 1, column 5: malformed crate");
+    assert_eq!(
+        error("  [A]\n 1   2"),
+        "line 1, column 3: crate is not below a stack label"
+    );
+    assert_eq!(
+        error("[A]\n    [B]\n 1   2"),
+        "line 1: crate in stack 1 is not resting on another crate"
+    );
+    assert_eq!(
+        error("[A]\n é"),
+        "line 2: expected stack label 1, found 'é'"
+    );
+
+    // Columns count characters, so wide characters do not shift stacks.
+    let accented = Cargo::parse(&lines("[é] [ü]\n 1   2"))?;
+    assert_eq!(accented.top_crates(), "éü");
+    assert_eq!(
+        error("[é]  [ü]\n 1   2"),
+        "line 1, column 6: crate is not below a stack label"
+    );
+
+    Ok(())
+}
+
+fn check_instruction_validation() -> Result<()> {
+    let (stack_lines, _) = read_input(Input::Test)?;
+    let original = Cargo::parse(&stack_lines)?;
+    let instructions = |text: &str| {
+        text.lines()
+            .map(|line| Instruction::from_str(line).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    // A bad instruction late in the list is reported by index, and a dry run
+    // leaves the cargo untouched.
+    let mut cargo = Cargo::parse(&stack_lines)?;
+    let bad = instructions("move 1 from 2 to 1\nmove 4 from 1 to 3\nmove 1 from 0 to 1");
+    assert_eq!(
+        cargo.apply(&bad, &mut CrateMover9000, Mode::DryRun),
+        Err(ApplyError {
+            index: 1,
+            cause: MoveError::NotEnoughCrates {
+                stack: 1,
+                available: 3,
+                requested: 4,
+            },
+        })
+    );
+    assert_eq!(cargo, original);
+
+    // Executing stops at the failing instruction, after the valid ones ran.
+    assert!(cargo
+        .apply(&bad, &mut CrateMover9001, Mode::Execute)
+        .is_err());
+    assert_eq!(cargo.top_crates(), "DCP");
+
+    let bad = instructions("move 1 from 1 to 2\nmove 1 from 0 to 1");
+    let error = cargo
+        .apply(&bad, &mut CrateMover9000, Mode::DryRun)
+        .unwrap_err();
+    assert_eq!(error.to_string(), "instruction 2: stack 0 does not exist");
+    let error = cargo
+        .apply(
+            &instructions("move 1 from 3 to 4"),
+            &mut CrateMover9000,
+            Mode::DryRun,
+        )
+        .unwrap_err();
+    assert_eq!(error.cause, MoveError::NoSuchStack(4));
+
+    Ok(())
+}
+
+fn check_cranes() -> Result<()> {
+    let solve_test = |arg: &str| -> Result<String> {
+        let mut crane = parse_crane(arg)?;
+        solve_with_crane(crane.as_mut(), Input::Test)
+    };
+
+    assert_eq!(solve_test("9000")?, "CMZ");
+    assert_eq!(solve_test("9001")?, "MCD");
+    assert_eq!(solve_test("limited:1")?, "CMZ");
+    assert_eq!(solve_test("limited:3")?, "MCD");
+    assert_eq!(solve_test("limited:2")?, "MCZ");
+    assert_eq!(solve_test("flipping")?, "CMN");
+
+    // Crates are flipped pairwise from the top of the lift.
+    let drawing: Vec<String> = ["[E]", "[D]", "[C]", "[B]", "[A]", " 1   2 "]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+    let mut cargo = Cargo::parse(&drawing)?;
+    let instr: Instruction = "move 5 from 1 to 2".parse()?;
+    FlippingCrane.move_crates(&mut cargo, &instr);
+    assert_eq!(cargo.stacks[1].crates, vec!['A', 'C', 'B', 'E', 'D']);
+
+    assert!(parse_crane("9002").is_err());
+    assert!(parse_crane("limited:0").is_err());
+
+    Ok(())
+}
+
+// Generate a cargo holding `num_crates` crates spread over `num_stacks`
+// stacks, plus `num_instructions` valid instructions moving up to `max_move`
+// crates each.
+fn generate_input(
+    num_stacks: usize,
+    num_crates: usize,
+    num_instructions: usize,
+    max_move: usize,
+    seed: u64,
+) -> (Cargo, Vec<Instruction>) {
+    let mut rng = Rng::new(seed);
+
+    let mut cargo = Cargo {
+        stacks: (0..num_stacks).map(|_| Stack::new()).collect(),
+    };
+    for _ in 0..num_crates {
+        let c = (b'A' + rng.below(26) as u8) as char;
+        cargo.stacks[rng.below(num_stacks)].push(c);
+    }
+
+    let mut heights: Vec<usize> = cargo.stacks.iter().map(|stack| stack.height()).collect();
+    let mut instructions = Vec::with_capacity(num_instructions);
+    while instructions.len() < num_instructions {
+        let from = rng.below(num_stacks);
+        let to = rng.below(num_stacks);
+        if heights[from] == 0 {
+            continue;
+        }
+        let number = 1 + rng.below(heights[from].min(max_move));
+        heights[from] -= number;
+        heights[to] += number;
+        instructions.push(Instruction {
+            number,
+            from: from + 1,
+            to: to + 1,
+        });
+    }
+
+    (cargo, instructions)
+}
+
+// Compare slice moves against moving one crate at a time (a crane with
+// capacity 1) on generated inputs of growing size. Run with `cargo run
+// --release -- bench`.
+fn benchmark() -> Result<()> {
+    for size in [10_000, 100_000, 1_000_000] {
+        let (cargo, instructions) = generate_input(9, size, size, 100, 0x5eed);
+
+        let mut results = Vec::new();
+        let cranes: [(&str, Box<dyn Crane>); 2] = [
+            ("one by one", Box::new(LimitedCrane { capacity: 1 })),
+            ("slice", Box::new(CrateMover9000)),
+        ];
+        for (name, mut crane) in cranes {
+            let mut cargo = Cargo {
+                stacks: cargo
+                    .stacks
+                    .iter()
+                    .map(|stack| Stack {
+                        crates: stack.crates.clone(),
+                    })
+                    .collect(),
+            };
+            let start = Instant::now();
+            cargo.apply(&instructions, crane.as_mut(), Mode::Execute)?;
+            println!(
+                "{:>9} crates/instructions, {:>10}: {} us",
+                size,
+                name,
+                start.elapsed().as_micros()
+            );
+            results.push(cargo);
         }
+        assert!(results[0] == results[1], "crane results differ");
     }
 
+    Ok(())
+}
+
+fn solve(part: Part, input: Input) -> Result<String> {
+    let mut crane: Box<dyn Crane> = match part {
+        Part::One => Box::new(CrateMover9000),
+        Part::Two => Box::new(CrateMover9001),
+    };
+    solve_with_crane(crane.as_mut(), input)
+}
+
+fn solve_with_crane(crane: &mut dyn Crane, input: Input) -> Result<String> {
+    let (stack_lines, instr_lines) = read_input(input)?;
+
+    let mut cargo = Cargo::parse(&stack_lines)?;
+
     let instructions: Vec<Instruction> = instr_lines
         .iter()
         .map(|line| Instruction::from_str(line).unwrap())
         .collect();
 
-    for instr in instructions {
-        match part {
-            Part::One => cargo.move_crates_9000(instr),
-            Part::Two => cargo.move_crates_9001(instr),
-        };
-    }
+    cargo.apply(&instructions, crane, Mode::DryRun)?;
+    cargo.apply(&instructions, crane, Mode::Execute)?;
 
     Ok(cargo.top_crates())
 }
@@ -142,6 +753,12 @@ fn solve(part: Part, input: Input) -> Result<String> {
 fn main() -> Result<()> {
     let start = Instant::now();
 
+    assert!(round_trip(Input::Test)?);
+    assert!(round_trip(Input::Puzzle)?);
+    check_drawing_parser()?;
+    check_instruction_validation()?;
+    check_cranes()?;
+
     let test1 = solve(Part::One, Input::Test)?;
     assert_eq!(test1, "CMZ");
 
@@ -154,6 +771,15 @@ fn main() -> Result<()> {
     let result2 = solve(Part::Two, Input::Puzzle)?;
     println!("part 2 result: {}", result2);
 
+    if let Some(arg) = std::env::args().nth(1) {
+        if arg == "bench" {
+            return benchmark();
+        }
+        let mut crane = parse_crane(&arg)?;
+        let result = solve_with_crane(crane.as_mut(), Input::Puzzle)?;
+        println!("crane {} result: {}", arg, result);
+    }
+
     println!("Finished in {} us", start.elapsed().as_micros());
     Ok(())
 }
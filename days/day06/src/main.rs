@@ -1,7 +1,9 @@
 use anyhow::Result;
-use core::panic;
-use std::collections::HashSet;
+use rng::Rng;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::time::Instant;
@@ -21,48 +23,397 @@ fn all_different(chars: &[char], len: usize) -> bool {
     set.len() == len
 }
 
-fn solve(part: Part, input: Input) -> Result<usize> {
+// Reference implementation: check every window from scratch.
+fn find_marker_naive(chars: &[char], marker_len: usize) -> Option<usize> {
+    (marker_len..=chars.len()).find(|&i| all_different(&chars[i - marker_len..i], marker_len))
+}
+
+// Sliding window over a datastream, fed one byte at a time. It keeps a count
+// per letter and the number of letters that occur more than once in the
+// window, so each byte is handled in constant time. Anything other than a
+// lowercase letter (such as a trailing newline) empties the window, so
+// markers never span it.
+struct MarkerDetector {
+    marker_len: usize,
+    window: VecDeque<u8>,
+    counts: [usize; 26],
+    duplicates: usize,
+    processed: usize,
+}
+
+impl MarkerDetector {
+    fn new(marker_len: usize) -> Self {
+        assert!(marker_len > 0, "a marker needs at least one byte");
+        MarkerDetector {
+            marker_len,
+            window: VecDeque::with_capacity(marker_len + 1),
+            counts: [0; 26],
+            duplicates: 0,
+            processed: 0,
+        }
+    }
+
+    // Returns the number of bytes processed so far if the last `marker_len`
+    // bytes are all different.
+    fn push(&mut self, b: u8) -> Option<usize> {
+        self.processed += 1;
+
+        if !b.is_ascii_lowercase() {
+            self.window.clear();
+            self.counts = [0; 26];
+            self.duplicates = 0;
+            return None;
+        }
+
+        let c = (b - b'a') as usize;
+        self.window.push_back(b);
+        self.counts[c] += 1;
+        if self.counts[c] == 2 {
+            self.duplicates += 1;
+        }
+
+        if self.window.len() > self.marker_len {
+            let old = (self.window.pop_front().unwrap() - b'a') as usize;
+            self.counts[old] -= 1;
+            if self.counts[old] == 1 {
+                self.duplicates -= 1;
+            }
+        }
+
+        if self.window.len() == self.marker_len && self.duplicates == 0 {
+            Some(self.processed)
+        } else {
+            None
+        }
+    }
+}
+
+fn find_marker(data: &[u8], marker_len: usize) -> Option<usize> {
+    let mut detector = MarkerDetector::new(marker_len);
+    data.iter().find_map(|&b| detector.push(b))
+}
+
+// Iterator over all marker positions in a stream, reading it in chunks so
+// each position is reported as soon as the chunk containing it arrives.
+struct Markers<R> {
+    reader: R,
+    detector: MarkerDetector,
+    chunk: Vec<u8>,
+    filled: usize,
+    next: usize,
+    done: bool,
+}
+
+impl<R: Read> Iterator for Markers<R> {
+    type Item = io::Result<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.next < self.filled {
+                let b = self.chunk[self.next];
+                self.next += 1;
+                if let Some(position) = self.detector.push(b) {
+                    return Some(Ok(position));
+                }
+            }
+
+            if self.done {
+                return None;
+            }
+
+            match self.reader.read(&mut self.chunk) {
+                Ok(0) => self.done = true,
+                Ok(n) => {
+                    self.filled = n;
+                    self.next = 0;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+fn markers<R: Read>(reader: R, marker_len: usize) -> Markers<R> {
+    Markers {
+        reader,
+        detector: MarkerDetector::new(marker_len),
+        chunk: vec![0; 4096],
+        filled: 0,
+        next: 0,
+        done: false,
+    }
+}
+
+fn check_markers() -> Result<()> {
+    let positions = |data: &str, marker_len| -> Result<Vec<usize>> {
+        Ok(markers(data.as_bytes(), marker_len).collect::<io::Result<_>>()?)
+    };
+
+    assert_eq!(positions("bvwbjplbgvbhsrlpgdmjqwftvncz", 4)?[0], 5);
+    assert_eq!(positions("abcabcd", 3)?, vec![3, 4, 5, 6, 7]);
+    assert_eq!(positions("aabbaab", 2)?, vec![3, 5, 7]);
+    assert_eq!(positions("abc\nabcd\n", 4)?, vec![8]);
+    assert!(positions("aaaa", 2)?.is_empty());
+
+    // Markers spanning a chunk boundary are still found.
+    let chained = "mjqj"
+        .as_bytes()
+        .chain("pqmgbljsphdztnvjfqwrcgsmlb".as_bytes());
+    assert_eq!(markers(chained, 4).next().transpose()?, Some(7));
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct Frame<'a> {
+    offset: usize,
+    marker: &'a [u8],
+    payload: &'a [u8],
+}
+
+// Splits a datastream into frames: a marker, followed by a payload that runs
+// until the next marker starts (or the stream ends). Bytes before the first
+// marker are noise and are skipped. A new marker only counts once it lies
+// entirely after the previous one.
+struct Frames<'a> {
+    data: &'a [u8],
+    marker_len: usize,
+    // Start of the marker opening the next frame, if one was found.
+    next_marker: Option<usize>,
+}
+
+impl<'a> Frames<'a> {
+    fn find_marker_from(&self, from: usize) -> Option<usize> {
+        find_marker(&self.data[from..], self.marker_len).map(|end| from + end - self.marker_len)
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.next_marker?;
+        let payload_start = offset + self.marker_len;
+        self.next_marker = self.find_marker_from(payload_start);
+        let payload_end = self.next_marker.unwrap_or(self.data.len());
+
+        Some(Frame {
+            offset,
+            marker: &self.data[offset..payload_start],
+            payload: &self.data[payload_start..payload_end],
+        })
+    }
+}
+
+fn frames(data: &[u8], marker_len: usize) -> Frames<'_> {
+    let mut frames = Frames {
+        data,
+        marker_len,
+        next_marker: None,
+    };
+    frames.next_marker = frames.find_marker_from(0);
+    frames
+}
+
+fn check_frames() {
+    let frame = |offset, marker: &'static str, payload: &'static str| Frame {
+        offset,
+        marker: marker.as_bytes(),
+        payload: payload.as_bytes(),
+    };
+
+    assert_eq!(
+        frames(b"aaabcdaaaefghbb", 4).collect::<Vec<_>>(),
+        vec![frame(2, "abcd", "aa"), frame(8, "aefg", "hbb")]
+    );
+    assert_eq!(
+        frames(b"mjqjpqmgbljsphdztnvjfqwrcgsmlb", 14).collect::<Vec<_>>(),
+        vec![frame(5, "qmgbljsphdztnv", "jfqwrcgsmlb")]
+    );
+    assert_eq!(frames(b"abab", 3).next(), None);
+}
+
+// Compare the sliding window against the per-window `HashSet` for every
+// window size. Random letters rarely contain a long marker, so large windows
+// scan (nearly) the whole stream. Run with `cargo run --release -- bench`.
+fn benchmark() {
+    let mut rng = Rng::new(0x5eed);
+    let data: Vec<u8> = (0..1_000_000)
+        .map(|_| b'a' + (rng.next_u64() % 26) as u8)
+        .collect();
+    let chars: Vec<char> = data.iter().map(|&b| b as char).collect();
+
+    for marker_len in 1..=26 {
+        let start = Instant::now();
+        let naive = find_marker_naive(&chars, marker_len);
+        let naive_time = start.elapsed().as_micros();
+
+        let start = Instant::now();
+        let sliding = find_marker(&data, marker_len);
+        let sliding_time = start.elapsed().as_micros();
+
+        assert_eq!(naive, sliding);
+        println!(
+            "window {:>2}: marker {:>7}, hash set {:>8} us, sliding {:>6} us",
+            marker_len,
+            sliding.map_or("none".to_string(), |i| i.to_string()),
+            naive_time,
+            sliding_time
+        );
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct NoMarkerFound {
+    line: usize,
+    marker_len: usize,
+}
+
+impl fmt::Display for NoMarkerFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: no marker of length {} found",
+            self.line, self.marker_len
+        )
+    }
+}
+
+impl std::error::Error for NoMarkerFound {}
+
+// Treat every line as a separate datastream and find its first marker.
+fn find_markers_per_line(
+    reader: impl BufRead,
+    marker_len: usize,
+) -> Result<Vec<Result<usize, NoMarkerFound>>> {
+    let mut results = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        results.push(
+            find_marker(line.as_bytes(), marker_len).ok_or(NoMarkerFound {
+                line: index + 1,
+                marker_len,
+            }),
+        );
+    }
+    Ok(results)
+}
+
+fn check_markers_per_line() -> Result<()> {
+    let results = find_markers_per_line("abc\naaaa\n\nabab".as_bytes(), 2)?;
+    assert_eq!(
+        results,
+        vec![
+            Ok(2),
+            Err(NoMarkerFound {
+                line: 2,
+                marker_len: 2
+            }),
+            Err(NoMarkerFound {
+                line: 3,
+                marker_len: 2
+            }),
+            Ok(2),
+        ]
+    );
+    assert_eq!(
+        results[1].as_ref().unwrap_err().to_string(),
+        "line 2: no marker of length 2 found"
+    );
+
+    Ok(())
+}
+
+fn solve(part: Part, input: Input) -> Result<Vec<Result<usize, NoMarkerFound>>> {
     let file = match input {
         Input::Test => File::open("test.txt")?,
         Input::Puzzle => File::open("input.txt")?,
     };
     let reader = BufReader::new(file);
-    let chars: Vec<char> = reader
-        .lines()
-        .map(|line| line.unwrap())
-        .next()
-        .unwrap()
-        .chars()
-        .collect();
 
     let marker_len = match part {
         Part::One => 4,
         Part::Two => 14,
     };
 
-    for i in marker_len..chars.len() {
-        if all_different(&chars[i - marker_len..i], marker_len) {
-            return Ok(i);
-        }
-    }
+    find_markers_per_line(reader, marker_len)
+}
 
-    panic!()
+// The marker length given on the command line, 4 by default.
+fn marker_len_arg(arg: Option<&String>) -> Result<usize> {
+    let marker_len = arg.map_or(Ok(4), |len| len.parse())?;
+    if marker_len == 0 {
+        anyhow::bail!("a marker needs at least one byte");
+    }
+    Ok(marker_len)
 }
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("bench") => {
+            benchmark();
+            return Ok(());
+        }
+        // Print every marker position of the given length in a file, or in
+        // stdin if no file is given, as soon as it is found.
+        Some("markers") => {
+            let marker_len = marker_len_arg(args.get(2))?;
+            let reader: Box<dyn Read> = match args.get(3) {
+                Some(path) => Box::new(File::open(path)?),
+                None => Box::new(io::stdin()),
+            };
+            for position in markers(reader, marker_len) {
+                println!("{}", position?);
+            }
+            return Ok(());
+        }
+        // Decode a file, or stdin, into frames opened by markers of the
+        // given length.
+        Some("frames") => {
+            let marker_len = marker_len_arg(args.get(2))?;
+            let mut data = Vec::new();
+            match args.get(3) {
+                Some(path) => File::open(path)?.read_to_end(&mut data)?,
+                None => io::stdin().read_to_end(&mut data)?,
+            };
+            for frame in frames(&data, marker_len) {
+                println!(
+                    "{:>6}: {} {}",
+                    frame.offset,
+                    String::from_utf8_lossy(frame.marker),
+                    String::from_utf8_lossy(frame.payload).trim_end()
+                );
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let start = Instant::now();
 
+    check_markers()?;
+    check_frames();
+
+    check_markers_per_line()?;
+
     let test1 = solve(Part::One, Input::Test)?;
-    assert_eq!(test1, 7);
+    assert_eq!(test1, vec![Ok(7), Ok(5), Ok(6), Ok(10), Ok(11)]);
 
-    let result1 = solve(Part::One, Input::Puzzle)?;
-    println!("part 1 result: {}", result1);
+    for result1 in solve(Part::One, Input::Puzzle)? {
+        println!("part 1 result: {}", result1?);
+    }
 
     let test2 = solve(Part::Two, Input::Test)?;
-    assert_eq!(test2, 19);
+    assert_eq!(test2, vec![Ok(19), Ok(23), Ok(23), Ok(29), Ok(26)]);
 
-    let result2 = solve(Part::Two, Input::Puzzle)?;
-    println!("part 2 result: {}", result2);
+    for result2 in solve(Part::Two, Input::Puzzle)? {
+        println!("part 2 result: {}", result2?);
+    }
 
     println!("Finished in {} us", start.elapsed().as_micros());
     Ok(())
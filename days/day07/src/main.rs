@@ -1,12 +1,14 @@
 use anyhow::Result;
-use core::panic;
-use std::borrow::Borrow;
-use std::cell::RefCell;
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::rc::Rc;
-use std::rc::Weak;
+use rng::Rng;
+use std::collections::HashSet;
+use std::env::temp_dir;
+use std::fmt;
+use std::fmt::Write;
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, File};
+use std::io;
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+use std::thread;
 use std::time::Instant;
 
 enum Part {
@@ -29,95 +31,1120 @@ impl SingleFile {
     fn new(name: &str, size: usize) -> Self {
         SingleFile {
             name: String::from(name),
-            size: size,
+            size,
         }
     }
 }
 
+type DirId = usize;
+
+// A directory, or a file identified by its directory and position in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entry {
+    Dir(DirId),
+    File { dir: DirId, index: usize },
+}
+
 #[derive(Debug)]
 struct Directory {
     name: String,
-    subdirs: Vec<Rc<RefCell<Directory>>>,
+    subdirs: Vec<DirId>,
     files: Vec<SingleFile>,
-    parent: Option<Weak<RefCell<Directory>>>,
+    parent: Option<DirId>,
+    // Total size of the directory including its subdirectories, filled in by
+    // `FileSystem::update_sizes` and cleared when anything below it changes.
+    size: Option<usize>,
 }
 
 impl Directory {
-    fn new(name: &str, parent: Option<Weak<RefCell<Directory>>>) -> Self {
+    fn new(name: &str, parent: Option<DirId>) -> Self {
         Directory {
             name: String::from(name),
             subdirs: Vec::new(),
             files: Vec::new(),
-            parent: parent,
+            parent,
+            size: None,
+        }
+    }
+}
+
+// All directories live in one arena and refer to each other by index, with
+// the root at index 0. Directories are only ever appended, so a directory's
+// index is always larger than its parent's.
+#[derive(Debug)]
+struct FileSystem {
+    dirs: Vec<Directory>,
+}
+
+impl FileSystem {
+    const ROOT: DirId = 0;
+
+    fn new() -> Self {
+        FileSystem {
+            dirs: vec![Directory::new("/", None)],
+        }
+    }
+
+    fn add_dir(&mut self, parent: DirId, name: &str) -> DirId {
+        let id = self.dirs.len();
+        self.dirs.push(Directory::new(name, Some(parent)));
+        self.dirs[parent].subdirs.push(id);
+        self.invalidate_size(parent);
+        id
+    }
+
+    fn add_file(&mut self, dir: DirId, name: &str, size: usize) {
+        self.dirs[dir].files.push(SingleFile::new(name, size));
+        self.invalidate_size(dir);
+    }
+
+    // Clear the cached size of a directory and its ancestors. A directory
+    // without a cached size never has ancestors with one, so we can stop at
+    // the first directory that is already invalid.
+    fn invalidate_size(&mut self, dir: DirId) {
+        let mut current = Some(dir);
+        while let Some(dir) = current {
+            if self.dirs[dir].size.take().is_none() {
+                break;
+            }
+            current = self.dirs[dir].parent;
         }
     }
 
-    fn get_dir(&self, name: &str) -> Rc<RefCell<Directory>> {
-        for dir in &self.subdirs {
-            if dir.borrow_mut().name == name {
-                return dir.clone();
+    // Compute the size of every directory whose size is not cached, in a
+    // single post-order pass. Since subdirectories come after their parent in
+    // the arena, walking it backwards visits children first.
+    fn update_sizes(&mut self) {
+        for dir in (0..self.dirs.len()).rev() {
+            if self.dirs[dir].size.is_some() {
+                continue;
             }
+            let size = self.dirs[dir].files.iter().map(|f| f.size).sum::<usize>()
+                + self.dirs[dir]
+                    .subdirs
+                    .iter()
+                    .map(|&subdir| self.dirs[subdir].size.unwrap())
+                    .sum::<usize>();
+            self.dirs[dir].size = Some(size);
         }
-        panic!()
     }
 
-    fn size(&self) -> usize {
-        self.files.iter().map(|f| f.size).sum::<usize>()
-            + self
+    fn get_dir(&self, dir: DirId, name: &str) -> Option<DirId> {
+        self.dirs[dir]
+            .subdirs
+            .iter()
+            .copied()
+            .find(|&subdir| self.dirs[subdir].name == name)
+    }
+
+    fn get_file(&self, dir: DirId, name: &str) -> Option<usize> {
+        self.dirs[dir].files.iter().position(|f| f.name == name)
+    }
+
+    fn parent(&self, dir: DirId) -> Option<DirId> {
+        self.dirs[dir].parent
+    }
+
+    // Uses the cached size if there is one, and walks the subtree otherwise.
+    fn size(&self, dir: DirId) -> usize {
+        if let Some(size) = self.dirs[dir].size {
+            return size;
+        }
+        self.dirs[dir].files.iter().map(|f| f.size).sum::<usize>()
+            + self.dirs[dir]
                 .subdirs
                 .iter()
-                .map(|dir| dir.borrow_mut().size())
+                .map(|&subdir| self.size(subdir))
                 .sum::<usize>()
     }
 
-    fn sum_subdirs_with_size(&self, at_most: usize) -> usize {
+    fn sum_subdirs_with_size(&self, dir: DirId, at_most: usize) -> usize {
         let mut result = 0;
-        if self.size() <= at_most {
-            result += self.size();
+        if self.size(dir) <= at_most {
+            result += self.size(dir);
         }
-        for dir in &self.subdirs {
-            let borrowed = dir.borrow_mut();
-            result += borrowed.sum_subdirs_with_size(at_most);
+        for &subdir in &self.dirs[dir].subdirs {
+            result += self.sum_subdirs_with_size(subdir, at_most);
         }
 
         result
     }
 
-    fn smallest_subdir_with_size(&self, at_least: usize) -> usize {
+    fn smallest_subdir_with_size(&self, dir: DirId, at_least: usize) -> usize {
         let mut result = usize::MAX;
 
-        let self_size = self.size();
+        let self_size = self.size(dir);
         if self_size >= at_least && self_size < result {
             result = self_size;
         }
-        for dir in &self.subdirs {
-            let borrowed = dir.borrow_mut();
-            let sub_result = borrowed.smallest_subdir_with_size(at_least);
+        for &subdir in &self.dirs[dir].subdirs {
+            let sub_result = self.smallest_subdir_with_size(subdir, at_least);
             if sub_result < result {
                 result = sub_result;
             }
         }
         result
     }
+
+    // Full path of a directory, such as `/a/e`.
+    fn path(&self, dir: DirId) -> String {
+        match self.dirs[dir].parent {
+            None => String::from("/"),
+            Some(parent) if parent == FileSystem::ROOT => format!("/{}", self.dirs[dir].name),
+            Some(parent) => format!("{}/{}", self.path(parent), self.dirs[dir].name),
+        }
+    }
+
+    fn entry_path(&self, entry: Entry) -> String {
+        match entry {
+            Entry::Dir(dir) => self.path(dir),
+            Entry::File { dir, index } => {
+                let name = &self.dirs[dir].files[index].name;
+                match dir {
+                    FileSystem::ROOT => format!("/{}", name),
+                    _ => format!("{}/{}", self.path(dir), name),
+                }
+            }
+        }
+    }
+
+    // Resolve an absolute path, or a path relative to `cwd`. Empty and `.`
+    // components are ignored and `..` at the root stays at the root.
+    fn resolve(&self, cwd: DirId, path: &str) -> Option<Entry> {
+        let mut entry = match path.starts_with('/') {
+            true => Entry::Dir(FileSystem::ROOT),
+            false => Entry::Dir(cwd),
+        };
+
+        for component in path.split('/') {
+            let dir = match entry {
+                Entry::Dir(dir) => dir,
+                Entry::File { .. } => return None,
+            };
+            entry = match component {
+                "" | "." => entry,
+                ".." => Entry::Dir(self.parent(dir).unwrap_or(FileSystem::ROOT)),
+                name => match self.get_dir(dir, name) {
+                    Some(subdir) => Entry::Dir(subdir),
+                    None => Entry::File {
+                        dir,
+                        index: self.get_file(dir, name)?,
+                    },
+                },
+            };
+        }
+
+        Some(entry)
+    }
+
+    // All directories and files with their full paths, depth first: each
+    // directory is followed by its files and then its subdirectories.
+    fn walk(&self) -> impl Iterator<Item = (String, Entry)> + '_ {
+        let mut pending = vec![FileSystem::ROOT];
+        std::iter::from_fn(move || {
+            let dir = pending.pop()?;
+            // Pushed in reverse so subdirectories are visited in order.
+            pending.extend(self.dirs[dir].subdirs.iter().rev());
+            let files =
+                (0..self.dirs[dir].files.len()).map(move |index| Entry::File { dir, index });
+            Some(std::iter::once(Entry::Dir(dir)).chain(files))
+        })
+        .flatten()
+        .map(|entry| (self.entry_path(entry), entry))
+    }
+
+    // Entries whose name matches a glob pattern with `*` and `?`, or whose
+    // full path matches if the pattern contains a `/`.
+    fn find(&self, pattern: &str) -> Vec<(String, Entry)> {
+        self.walk()
+            .filter(|(path, _)| {
+                let name = match pattern.contains('/') {
+                    true => path.as_str(),
+                    false => path.rsplit('/').next().unwrap(),
+                };
+                glob_match(pattern.as_bytes(), name.as_bytes())
+            })
+            .collect()
+    }
+
+    // Render the hierarchy the way the puzzle describes it, with the entries
+    // of each directory sorted by name. Entries deeper than `max_depth` below
+    // the root are left out.
+    fn render_tree(&self, max_depth: Option<usize>) -> String {
+        let mut out = String::new();
+        self.render_tree_dir(FileSystem::ROOT, 0, max_depth, &mut out);
+        out
+    }
+
+    fn render_tree_dir(
+        &self,
+        dir: DirId,
+        depth: usize,
+        max_depth: Option<usize>,
+        out: &mut String,
+    ) {
+        let indent = "  ".repeat(depth);
+        writeln!(out, "{}- {} (dir)", indent, self.dirs[dir].name).unwrap();
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return;
+        }
+
+        let mut entries: Vec<(&str, Option<DirId>, usize)> = self.dirs[dir]
+            .subdirs
+            .iter()
+            .map(|&subdir| (self.dirs[subdir].name.as_str(), Some(subdir), 0))
+            .chain(
+                self.dirs[dir]
+                    .files
+                    .iter()
+                    .map(|f| (f.name.as_str(), None, f.size)),
+            )
+            .collect();
+        entries.sort_by_key(|&(name, _, _)| name);
+
+        for (name, subdir, size) in entries {
+            match subdir {
+                Some(subdir) => self.render_tree_dir(subdir, depth + 1, max_depth, out),
+                None => writeln!(out, "{}  - {} (file, size={})", indent, name, size).unwrap(),
+            }
+        }
+    }
+
+    // List directories with their total size like `du -h`, largest first.
+    // Directories deeper than `max_depth` below the root are left out.
+    fn render_du(&self, max_depth: Option<usize>) -> String {
+        let mut dirs: Vec<(usize, String)> = Vec::new();
+        let mut pending = vec![(FileSystem::ROOT, 0)];
+        while let Some((dir, depth)) = pending.pop() {
+            dirs.push((self.size(dir), self.path(dir)));
+            if max_depth.is_none_or(|max_depth| depth < max_depth) {
+                pending.extend(
+                    self.dirs[dir]
+                        .subdirs
+                        .iter()
+                        .map(|&subdir| (subdir, depth + 1)),
+                );
+            }
+        }
+        dirs.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        dirs.iter()
+            .map(|(size, path)| format!("{}\t{}\n", human_size(*size), path))
+            .collect()
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            glob_match(rest, text) || (!text.is_empty() && glob_match(pattern, &text[1..]))
+        }
+        (Some((b'?', rest)), Some((_, text_rest))) => glob_match(rest, text_rest),
+        (Some((p, rest)), Some((t, text_rest))) => p == t && glob_match(rest, text_rest),
+        _ => false,
+    }
+}
+
+fn check_queries() -> Result<()> {
+    let fs = build_filesystem(Input::Test)?;
+    let root = FileSystem::ROOT;
+    let path = |entry: Option<Entry>| entry.map(|entry| fs.entry_path(entry));
+
+    assert_eq!(path(fs.resolve(root, "/a/e")), Some(String::from("/a/e")));
+    assert_eq!(
+        path(fs.resolve(root, "a/e/../f")),
+        Some(String::from("/a/f"))
+    );
+    let e = fs.get_dir(fs.get_dir(root, "a").unwrap(), "e").unwrap();
+    assert_eq!(
+        path(fs.resolve(e, "../../d/./k")),
+        Some(String::from("/d/k"))
+    );
+    assert_eq!(fs.resolve(e, "/.."), Some(Entry::Dir(root)));
+    assert_eq!(fs.resolve(root, "/x"), None);
+    assert_eq!(fs.resolve(root, "b.txt/x"), None);
+
+    assert_eq!(fs.walk().count(), 14);
+    let find = |pattern| {
+        fs.find(pattern)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(find("*.txt"), vec!["/b.txt"]);
+    assert_eq!(find("d*"), vec!["/d", "/d/d.log", "/d/d.ext"]);
+    assert_eq!(find("/a/?"), vec!["/a/f", "/a/g", "/a/e"]);
+    assert!(find("*.zip").is_empty());
+
+    Ok(())
+}
+
+// Format a size with binary units the way `du -h` does, rounding up: one
+// decimal below 10, whole numbers above.
+fn human_size(size: usize) -> String {
+    let units = ["", "K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    // Round first, so values just under 10 that round up to it lose their
+    // decimal.
+    let value = (value * 10.0).ceil() / 10.0;
+    if unit == 0 {
+        format!("{}", size)
+    } else if value < 10.0 {
+        format!("{:.1}{}", value, units[unit])
+    } else {
+        format!("{}{}", value.ceil(), units[unit])
+    }
+}
+
+fn check_rendering() -> Result<()> {
+    let fs = build_filesystem(Input::Test)?;
+
+    assert_eq!(
+        fs.render_tree(None),
+        "\
+- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+    );
+    assert_eq!(
+        fs.render_tree(Some(1)),
+        "\
+- / (dir)
+  - a (dir)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+"
+    );
+
+    assert_eq!(fs.render_du(None), "47M\t/\n24M\t/d\n93K\t/a\n584\t/a/e\n");
+    assert_eq!(fs.render_du(Some(0)), "47M\t/\n");
+
+    for (size, human) in [
+        (1023, "1023"),
+        (1024, "1.0K"),
+        (9_217, "9.1K"),
+        (10_200, "10K"),
+        (10_240, "10K"),
+        (10_241, "11K"),
+    ] {
+        assert_eq!(human_size(size), human, "{}", size);
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum PlanMode {
+    // Delete the smallest single directory that frees enough space.
+    SingleDir,
+    // Delete the set of directories, none inside another, that frees enough
+    // space while freeing as little as possible.
+    DirSet,
+}
+
+#[derive(Debug, PartialEq)]
+struct CleanupPlan {
+    dirs: Vec<DirId>,
+    freed: usize,
+}
+
+impl CleanupPlan {
+    fn paths(&self, fs: &FileSystem) -> Vec<String> {
+        self.dirs.iter().map(|&dir| fs.path(dir)).collect()
+    }
+}
+
+// Decides what to delete so that at least `required` space is free on a disk
+// of `capacity`.
+struct CleanupPlanner {
+    capacity: usize,
+    required: usize,
+}
+
+impl CleanupPlanner {
+    // Returns `None` if no deletion can free enough space. The plan is empty if
+    // enough space is free already.
+    fn plan(&self, fs: &FileSystem, mode: PlanMode) -> Option<CleanupPlan> {
+        let used = fs.size(FileSystem::ROOT);
+        if used > self.capacity || self.required > self.capacity {
+            return None;
+        }
+        let needed = self.required.saturating_sub(self.capacity - used);
+        if needed == 0 {
+            return Some(CleanupPlan {
+                dirs: Vec::new(),
+                freed: 0,
+            });
+        }
+
+        match mode {
+            PlanMode::SingleDir => (0..fs.dirs.len())
+                .filter(|&dir| fs.size(dir) >= needed)
+                .min_by_key(|&dir| fs.size(dir))
+                .map(|dir| CleanupPlan {
+                    dirs: vec![dir],
+                    freed: fs.size(dir),
+                }),
+            PlanMode::DirSet => {
+                let mut search = DirSetSearch::new(fs, needed);
+                let mut best = None;
+                search.run(0, 0, &mut Vec::new(), &mut best);
+                best
+            }
+        }
+    }
+}
+
+// Branch-and-bound search for the set of non-nested directories that frees
+// the least space that is still enough. Directories are visited in pre-order;
+// at each one we either delete it and skip its subtree, or descend into it.
+// What can be found from a position only depends on the space freed so far,
+// so each `(position, freed)` pair is searched once; this keeps directories
+// of equal size from multiplying the work.
+struct DirSetSearch {
+    needed: usize,
+    // Directories in pre-order, with their size and the position just past
+    // their subtree.
+    order: Vec<DirId>,
+    sizes: Vec<usize>,
+    subtree_end: Vec<usize>,
+    // Most space that can still be freed from a position onwards: deleting
+    // every remaining top-level directory.
+    reachable: Vec<usize>,
+    searched: HashSet<(usize, usize)>,
+}
+
+impl DirSetSearch {
+    fn new(fs: &FileSystem, needed: usize) -> Self {
+        let mut order = Vec::new();
+        let mut pending = vec![FileSystem::ROOT];
+        while let Some(dir) = pending.pop() {
+            order.push(dir);
+            pending.extend(fs.dirs[dir].subdirs.iter().rev());
+        }
+
+        let mut position = vec![0; fs.dirs.len()];
+        for (i, &dir) in order.iter().enumerate() {
+            position[dir] = i;
+        }
+
+        let n = order.len();
+        let sizes: Vec<usize> = order.iter().map(|&dir| fs.size(dir)).collect();
+        let mut subtree_end = vec![0; n];
+        let mut reachable = vec![0; n + 1];
+        for i in (0..n).rev() {
+            subtree_end[i] = match fs.dirs[order[i]].subdirs.last() {
+                Some(&last) => subtree_end[position[last]],
+                None => i + 1,
+            };
+            reachable[i] = sizes[i] + reachable[subtree_end[i]];
+        }
+
+        DirSetSearch {
+            needed,
+            order,
+            sizes,
+            subtree_end,
+            reachable,
+            searched: HashSet::new(),
+        }
+    }
+
+    fn run(
+        &mut self,
+        i: usize,
+        freed: usize,
+        chosen: &mut Vec<DirId>,
+        best: &mut Option<CleanupPlan>,
+    ) {
+        if freed >= self.needed {
+            if best.as_ref().is_none_or(|best| freed < best.freed) {
+                *best = Some(CleanupPlan {
+                    dirs: chosen.clone(),
+                    freed,
+                });
+            }
+            return;
+        }
+        if i == self.order.len() || freed + self.reachable[i] < self.needed {
+            return;
+        }
+        // Nothing can beat freeing exactly what is needed.
+        if best.as_ref().is_some_and(|best| best.freed == self.needed) {
+            return;
+        }
+        if !self.searched.insert((i, freed)) {
+            return;
+        }
+
+        chosen.push(self.order[i]);
+        self.run(self.subtree_end[i], freed + self.sizes[i], chosen, best);
+        chosen.pop();
+
+        self.run(i + 1, freed, chosen, best);
+    }
+}
+
+fn check_planner() -> Result<()> {
+    let fs = build_filesystem(Input::Test)?;
+    let plan = |capacity, required, mode| {
+        CleanupPlanner { capacity, required }
+            .plan(&fs, mode)
+            .map(|plan| (plan.paths(&fs), plan.freed))
+    };
+
+    assert_eq!(
+        plan(70_000_000, 30_000_000, PlanMode::SingleDir),
+        Some((vec![String::from("/d")], 24933642))
+    );
+    assert_eq!(
+        plan(70_000_000, 30_000_000, PlanMode::DirSet),
+        Some((vec![String::from("/d")], 24933642))
+    );
+    assert_eq!(
+        plan(48_381_165, 25_000_000, PlanMode::SingleDir),
+        Some((vec![String::from("/")], 48381165))
+    );
+    assert_eq!(
+        plan(48_381_165, 25_000_000, PlanMode::DirSet),
+        Some((vec![String::from("/a"), String::from("/d")], 25028495))
+    );
+    assert_eq!(
+        plan(70_000_000, 20_000_000, PlanMode::SingleDir),
+        Some((vec![], 0))
+    );
+    assert_eq!(plan(70_000_000, 80_000_000, PlanMode::DirSet), None);
+    assert_eq!(plan(40_000_000, 1, PlanMode::DirSet), None);
+
+    // Many directories of the same size, where no set frees exactly what is
+    // needed: without remembering searched states this takes exponential time.
+    let mut log = String::from("$ cd /\n$ ls\n");
+    for i in 0..40 {
+        writeln!(log, "dir d{}", i).unwrap();
+    }
+    for i in 0..40 {
+        writeln!(log, "$ cd d{}\n$ ls\n2 f\n$ cd ..", i).unwrap();
+    }
+    let siblings = replay(&log)?;
+    let plan = CleanupPlanner {
+        capacity: 100,
+        required: 61,
+    }
+    .plan(&siblings, PlanMode::DirSet)
+    .unwrap();
+    assert_eq!((plan.dirs.len(), plan.freed), (21, 42));
+
+    Ok(())
+}
+
+// Names come from the terminal log, so make sure they cannot escape the
+// directory they are written to.
+fn check_component(name: &str) -> io::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\0') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a valid file name", name),
+        ));
+    }
+    Ok(())
+}
+
+// The terminal log separates names from commands and sizes by whitespace,
+// so names that contain any cannot be written to a transcript.
+fn check_log_name(name: &str) -> io::Result<()> {
+    check_component(name)?;
+    if name.contains(char::is_whitespace) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' contains whitespace", name),
+        ));
+    }
+    Ok(())
+}
+
+impl FileSystem {
+    // Recreate the tree below `target`, with sparse files of the recorded
+    // sizes.
+    fn export_dir(&self, target: &Path) -> io::Result<()> {
+        self.export_dir_at(FileSystem::ROOT, target)
+    }
+
+    fn export_dir_at(&self, dir: DirId, target: &Path) -> io::Result<()> {
+        create_dir_all(target)?;
+        for file in &self.dirs[dir].files {
+            check_component(&file.name)?;
+            File::create(target.join(&file.name))?.set_len(file.size as u64)?;
+        }
+        for &subdir in &self.dirs[dir].subdirs {
+            check_component(&self.dirs[subdir].name)?;
+            self.export_dir_at(subdir, &target.join(&self.dirs[subdir].name))?;
+        }
+        Ok(())
+    }
+
+    // Write the tree as a ustar archive. Files are filled with zeros.
+    fn export_tar(&self, out: &mut impl io::Write) -> io::Result<()> {
+        for (path, entry) in self.walk() {
+            let path = path.trim_start_matches('/');
+            match entry {
+                Entry::Dir(FileSystem::ROOT) => continue,
+                Entry::Dir(dir) => {
+                    check_component(&self.dirs[dir].name)?;
+                    out.write_all(&tar_header(&format!("{}/", path), 0, b'5')?)?;
+                }
+                Entry::File { dir, index } => {
+                    let file = &self.dirs[dir].files[index];
+                    check_component(&file.name)?;
+                    out.write_all(&tar_header(path, file.size, b'0')?)?;
+                    let padded = file.size.div_ceil(512) * 512;
+                    io::copy(&mut io::repeat(0).take(padded as u64), out)?;
+                }
+            }
+        }
+        out.write_all(&[0; 1024])
+    }
+
+    // Build a tree from a directory on disk. Only regular files and
+    // directories are taken over, in name order.
+    fn import_dir(source: &Path) -> io::Result<FileSystem> {
+        let mut fs = FileSystem::new();
+        fs.import_dir_at(FileSystem::ROOT, source)?;
+        fs.update_sizes();
+        Ok(fs)
+    }
+
+    fn import_dir_at(&mut self, dir: DirId, source: &Path) -> io::Result<()> {
+        let mut entries = read_dir(source)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name().into_string().map_err(|name| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{:?} is not valid UTF-8", name),
+                )
+            })?;
+            check_log_name(&name)?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                let subdir = self.add_dir(dir, &name);
+                self.import_dir_at(subdir, &entry.path())?;
+            } else if file_type.is_file() {
+                self.add_file(dir, &name, entry.metadata()?.len() as usize);
+            }
+        }
+        Ok(())
+    }
+
+    // A terminal log that replays into this tree: every directory is listed
+    // once, subdirectories first.
+    fn transcript(&self) -> String {
+        let mut out = String::from("$ cd /\n");
+        self.transcript_at(FileSystem::ROOT, &mut out);
+        out
+    }
+
+    fn transcript_at(&self, dir: DirId, out: &mut String) {
+        out.push_str("$ ls\n");
+        for &subdir in &self.dirs[dir].subdirs {
+            writeln!(out, "dir {}", self.dirs[subdir].name).unwrap();
+        }
+        for file in &self.dirs[dir].files {
+            writeln!(out, "{} {}", file.size, file.name).unwrap();
+        }
+        for &subdir in &self.dirs[dir].subdirs {
+            writeln!(out, "$ cd {}", self.dirs[subdir].name).unwrap();
+            self.transcript_at(subdir, out);
+            out.push_str("$ cd ..\n");
+        }
+    }
+}
+
+fn tar_header(path: &str, size: usize, typeflag: u8) -> io::Result<[u8; 512]> {
+    // Paths longer than the name field are split over the prefix field at a
+    // `/`.
+    let (prefix, name) = match path.len() {
+        0..=100 => ("", path),
+        _ => path
+            .char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+            .map(|(i, _)| (&path[..i], &path[i + 1..]))
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("'{}' is too long for a tar archive", path),
+                )
+            })?,
+    };
+
+    // The size field holds 11 octal digits.
+    if size >= 1 << 33 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is too large for a tar archive", path),
+        ));
+    }
+
+    let mut header = [0u8; 512];
+    let mut field =
+        |offset: usize, value: &[u8]| header[offset..offset + value.len()].copy_from_slice(value);
+    field(0, name.as_bytes());
+    field(
+        100,
+        match typeflag {
+            b'5' => b"0000755\0",
+            _ => b"0000644\0",
+        },
+    );
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{:011o}\0", size).as_bytes());
+    field(136, b"00000000000\0");
+    field(148, b"        ");
+    field(156, &[typeflag]);
+    field(257, b"ustar\0");
+    field(263, b"00");
+    field(345, prefix.as_bytes());
+
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+fn check_export() -> Result<()> {
+    let fs = build_filesystem(Input::Test)?;
+
+    // Export, import again and compare.
+    let target = temp_dir().join(format!("day07-export-{}", std::process::id()));
+    fs.export_dir(&target)?;
+    let imported = FileSystem::import_dir(&target);
+    remove_dir_all(&target)?;
+    let imported = imported?;
+    assert_eq!(imported.render_tree(None), fs.render_tree(None));
+
+    // The synthetic transcript replays into the same tree.
+    let replayed = replay(&imported.transcript())?;
+    assert_eq!(replayed.render_tree(None), fs.render_tree(None));
+    assert_eq!(
+        replayed.sum_subdirs_with_size(FileSystem::ROOT, 100000),
+        95437
+    );
+
+    // Every directory except the root and every file gets a header, and file
+    // contents are padded to whole blocks.
+    let small = replay("$ ls\ndir a\n600 b.txt\n$ cd a\n$ ls\n10 c")?;
+    let mut archive = Vec::new();
+    small.export_tar(&mut archive)?;
+    assert_eq!(archive.len(), 3 * 512 + 1024 + 512 + 1024);
+    assert_eq!(&archive[..8], b"b.txt\0\0\0");
+    assert_eq!(&archive[1536..1539], b"a/\0");
+
+    // Sizes from 8 GiB on do not fit the header's size field.
+    let largest = tar_header("big", (1 << 33) - 1, b'0')?;
+    assert_eq!(&largest[124..136], b"77777777777\0");
+    assert_eq!(
+        tar_header("big", 1 << 33, b'0').unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+
+    // Names that would not survive a transcript are rejected on import.
+    let source = temp_dir().join(format!("day07-import-{}", std::process::id()));
+    create_dir_all(source.join("my dir"))?;
+    File::create(source.join("my dir").join("a b.txt"))?;
+    let imported = FileSystem::import_dir(&source);
+    remove_dir_all(&source)?;
+    assert_eq!(
+        imported.map(|_| ()).unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+
+    let mut unsafe_fs = FileSystem::new();
+    unsafe_fs.add_dir(FileSystem::ROOT, "..");
+    assert!(unsafe_fs.export_tar(&mut Vec::new()).is_err());
+
+    Ok(())
+}
+
+struct GeneratorConfig {
+    seed: u64,
+    max_depth: usize,
+    max_fan_out: usize,
+    max_files: usize,
+    // Files get 1 to `max_file_size` bytes, so this must be at least 1.
+    max_file_size: usize,
+}
+
+// The generator's own model of the tree, kept separate from `FileSystem` so
+// the expected answers do not depend on the code under test.
+struct GeneratedDir {
+    name: String,
+    files: Vec<(String, usize)>,
+    subdirs: Vec<GeneratedDir>,
+}
+
+impl GeneratedDir {
+    fn random(name: String, depth: usize, config: &GeneratorConfig, rng: &mut Rng) -> Self {
+        let mut names = HashSet::new();
+        let mut unique_name = |rng: &mut Rng, extension: bool| loop {
+            let len = 1 + rng.below(8);
+            let mut name: String = (0..len)
+                .map(|_| (b'a' + rng.below(26) as u8) as char)
+                .collect();
+            if extension && rng.below(2) == 0 {
+                name.push_str(&format!(".{}", ["txt", "dat", "log", "lst"][rng.below(4)]));
+            }
+            if names.insert(name.clone()) {
+                return name;
+            }
+        };
+
+        let num_files = rng.below(config.max_files + 1);
+        let files = (0..num_files)
+            .map(|_| (unique_name(rng, true), 1 + rng.below(config.max_file_size)))
+            .collect();
+
+        let num_subdirs = match depth < config.max_depth {
+            true => rng.below(config.max_fan_out + 1),
+            false => 0,
+        };
+        let subdirs = (0..num_subdirs)
+            .map(|_| {
+                let name = unique_name(rng, false);
+                GeneratedDir::random(name, depth + 1, config, rng)
+            })
+            .collect();
+
+        GeneratedDir {
+            name,
+            files,
+            subdirs,
+        }
+    }
+
+    // Total size of this directory, after pushing the sizes of all
+    // directories in its subtree (itself included) to `sizes`.
+    fn collect_sizes(&self, sizes: &mut Vec<usize>) -> usize {
+        let size = self.files.iter().map(|(_, size)| size).sum::<usize>()
+            + self
+                .subdirs
+                .iter()
+                .map(|subdir| subdir.collect_sizes(sizes))
+                .sum::<usize>();
+        sizes.push(size);
+        size
+    }
+
+    // Write the listing and visit the subdirectories in random order. The
+    // listing is sometimes repeated, and sometimes the way back up is a
+    // `cd /` followed by the path down again.
+    fn write_transcript(&self, path: &mut Vec<String>, rng: &mut Rng, out: &mut String) {
+        let mut listing: Vec<String> = self
+            .subdirs
+            .iter()
+            .map(|subdir| format!("dir {}", subdir.name))
+            .chain(
+                self.files
+                    .iter()
+                    .map(|(name, size)| format!("{} {}", size, name)),
+            )
+            .collect();
+        for _ in 0..1 + (rng.below(5) == 0) as usize {
+            rng.shuffle(&mut listing);
+            out.push_str("$ ls\n");
+            for line in &listing {
+                writeln!(out, "{}", line).unwrap();
+            }
+        }
+
+        let mut order: Vec<&GeneratedDir> = self.subdirs.iter().collect();
+        rng.shuffle(&mut order);
+        for subdir in order {
+            writeln!(out, "$ cd {}", subdir.name).unwrap();
+            path.push(subdir.name.clone());
+            subdir.write_transcript(path, rng, out);
+            path.pop();
+
+            if rng.below(4) == 0 {
+                out.push_str("$ cd /\n");
+                for name in path.iter() {
+                    writeln!(out, "$ cd {}", name).unwrap();
+                }
+            } else {
+                out.push_str("$ cd ..\n");
+            }
+        }
+    }
+}
+
+struct Generated {
+    transcript: String,
+    root_size: usize,
+    part1: usize,
+    // `None` if the tree does not fit on the 70M disk.
+    part2: Option<usize>,
+}
+
+fn generate(config: &GeneratorConfig) -> Generated {
+    let mut rng = Rng::new(config.seed);
+    let root = GeneratedDir::random(String::from("/"), 0, config, &mut rng);
+
+    let mut transcript = String::from("$ cd /\n");
+    root.write_transcript(&mut Vec::new(), &mut rng, &mut transcript);
+
+    let mut sizes = Vec::new();
+    let root_size = root.collect_sizes(&mut sizes);
+    let part1 = sizes.iter().filter(|&&size| size <= 100000).sum();
+    let part2 = match root_size {
+        0..=40_000_000 => Some(0),
+        70_000_001.. => None,
+        _ => sizes
+            .iter()
+            .copied()
+            .filter(|&size| size >= root_size - 40_000_000)
+            .min(),
+    };
+
+    Generated {
+        transcript,
+        root_size,
+        part1,
+        part2,
+    }
+}
+
+fn check_generated() -> Result<()> {
+    let planner = CleanupPlanner {
+        capacity: 70_000_000,
+        required: 30_000_000,
+    };
+
+    for seed in 0..200 {
+        // Shapes range from trees with only small directories to ones that
+        // no longer fit on the disk.
+        let (max_depth, max_fan_out, max_files, max_file_size) = [
+            (2, 3, 3, 50_000),
+            (3, 3, 4, 400_000),
+            (4, 5, 5, 700_000),
+            (5, 3, 5, 8_000_000),
+        ][seed as usize % 4];
+        let config = GeneratorConfig {
+            seed,
+            max_depth,
+            max_fan_out,
+            max_files,
+            max_file_size,
+        };
+        let generated = generate(&config);
+
+        let fs = replay(&generated.transcript)?;
+        assert_eq!(fs.size(FileSystem::ROOT), generated.root_size);
+        assert_eq!(
+            fs.sum_subdirs_with_size(FileSystem::ROOT, 100000),
+            generated.part1
+        );
+        assert_eq!(
+            planner
+                .plan(&fs, PlanMode::SingleDir)
+                .map(|plan| plan.freed),
+            generated.part2
+        );
+    }
+
+    // Every seed gives its own tree, 0 included.
+    let config = |seed| GeneratorConfig {
+        seed,
+        max_depth: 3,
+        max_fan_out: 3,
+        max_files: 3,
+        max_file_size: 1000,
+    };
+    assert_ne!(
+        generate(&config(0)).transcript,
+        generate(&config(1)).transcript
+    );
+
+    Ok(())
+}
+
+// Build a chain of `depth` nested directories, each holding one file.
+fn generate_deep_tree(depth: usize) -> FileSystem {
+    let mut fs = FileSystem::new();
+    let mut dir = FileSystem::ROOT;
+    for level in 0..depth {
+        fs.add_file(dir, "f", level + 1);
+        dir = fs.add_dir(dir, "d");
+    }
+    fs
+}
+
+// Compare the analysis with and without cached sizes on deep trees, where
+// recomputing sizes at every level is quadratic. Run with `cargo run
+// --release -- bench`.
+fn benchmark() {
+    for depth in [1_000, 5_000, 10_000] {
+        let mut fs = generate_deep_tree(depth);
+
+        let start = Instant::now();
+        let uncached = (
+            fs.sum_subdirs_with_size(FileSystem::ROOT, 100000),
+            fs.smallest_subdir_with_size(FileSystem::ROOT, depth),
+        );
+        let uncached_time = start.elapsed().as_micros();
+
+        let start = Instant::now();
+        fs.update_sizes();
+        let cached = (
+            fs.sum_subdirs_with_size(FileSystem::ROOT, 100000),
+            fs.smallest_subdir_with_size(FileSystem::ROOT, depth),
+        );
+        let cached_time = start.elapsed().as_micros();
+
+        assert_eq!(uncached, cached);
+        println!(
+            "depth {:>6}: uncached {:>9} us, cached {:>6} us",
+            depth, uncached_time, cached_time
+        );
+    }
 }
 
+#[derive(Clone, Copy)]
 enum Command {
     Cd,
     Ls,
 }
 
 impl Command {
-    fn parse(text: &str) -> Self {
+    fn parse(text: &str) -> Option<Self> {
         match text {
-            "cd" => Self::Cd,
-            "ls" => Self::Ls,
-            _ => panic!(),
+            "cd" => Some(Self::Cd),
+            "ls" => Some(Self::Ls),
+            _ => None,
         }
     }
 }
 
 enum Argument {
-    Empty,
     Name(String),
     Root,
     Parent,
@@ -133,9 +1160,9 @@ impl Argument {
     }
 }
 
-struct TerminalInput {
-    cmd: Command,
-    arg: Argument,
+enum TerminalInput {
+    Cd(Argument),
+    Ls,
 }
 
 enum OutputType {
@@ -153,110 +1180,398 @@ enum TerminalLine {
     Output(TerminalOutput),
 }
 
-fn parse_terminal(reader: BufReader<File>) -> Vec<TerminalLine> {
-    reader
-        .lines()
-        .map(|line| line.unwrap())
-        .map(|line| {
-            let parts: Vec<&str> = line.split_ascii_whitespace().collect();
-            match parts[0] {
-                "$" => match parts.len() {
-                    2 => TerminalLine::Input(TerminalInput {
-                        cmd: Command::parse(parts[1]),
-                        arg: Argument::Empty,
-                    }),
-                    3 => TerminalLine::Input(TerminalInput {
-                        cmd: Command::parse(parts[1]),
-                        arg: Argument::parse(parts[2]),
-                    }),
-                    _ => panic!(),
-                },
-                _ => match parts[0].parse::<usize>() {
-                    Ok(size) => TerminalLine::Output(TerminalOutput {
-                        typ: OutputType::Size(size),
-                        name: String::from(parts[1]),
-                    }),
-                    Err(_) => TerminalLine::Output(TerminalOutput {
-                        typ: OutputType::Dir,
-                        name: String::from(parts[1]),
-                    }),
-                },
+// Problems found while replaying a terminal log. Line numbers start at 1.
+#[derive(Debug, PartialEq)]
+enum ReplayError {
+    UnknownCommand {
+        line: usize,
+        command: String,
+    },
+    MissingArgument {
+        line: usize,
+    },
+    UnexpectedArgument {
+        line: usize,
+    },
+    MalformedLine {
+        line: usize,
+    },
+    OutputWithoutLs {
+        line: usize,
+    },
+    AboveRoot {
+        line: usize,
+    },
+    FileAndDir {
+        line: usize,
+        name: String,
+    },
+    InvalidName {
+        line: usize,
+        name: String,
+    },
+    SizeMismatch {
+        line: usize,
+        name: String,
+        old: usize,
+        new: usize,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::UnknownCommand { line, command } => {
+                write!(f, "line {}: unknown command '{}'", line, command)
             }
-        })
-        .collect()
+            ReplayError::MissingArgument { line } => {
+                write!(f, "line {}: cd needs a directory", line)
+            }
+            ReplayError::UnexpectedArgument { line } => {
+                write!(f, "line {}: unexpected argument", line)
+            }
+            ReplayError::MalformedLine { line } => write!(f, "line {}: malformed line", line),
+            ReplayError::OutputWithoutLs { line } => {
+                write!(f, "line {}: output without a preceding ls", line)
+            }
+            ReplayError::AboveRoot { line } => write!(f, "line {}: cd .. at the root", line),
+            ReplayError::FileAndDir { line, name } => {
+                write!(
+                    f,
+                    "line {}: '{}' is both a file and a directory",
+                    line, name
+                )
+            }
+            ReplayError::InvalidName { line, name } => {
+                write!(f, "line {}: '{}' is not a valid name", line, name)
+            }
+            ReplayError::SizeMismatch {
+                line,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "line {}: '{}' was listed with size {} before, now {}",
+                line, name, old, new
+            ),
+        }
+    }
 }
 
-fn solve(part: Part, input: Input) -> Result<usize> {
-    let file = match input {
-        Input::Test => File::open("test.txt")?,
-        Input::Puzzle => File::open("input.txt")?,
-    };
-    let reader = BufReader::new(file);
-    let lines: Vec<TerminalLine> = parse_terminal(reader);
+impl std::error::Error for ReplayError {}
 
-    let root = Directory::new("root", None);
-    let root = Rc::new(RefCell::new(root));
+// Entries are stored under their names, so `.`, `..` and anything with a `/`
+// would break paths later.
+fn check_name(line: usize, name: String) -> Result<String, ReplayError> {
+    match name.as_str() {
+        "." | ".." => Err(ReplayError::InvalidName { line, name }),
+        _ if name.contains('/') => Err(ReplayError::InvalidName { line, name }),
+        _ => Ok(name),
+    }
+}
 
-    let mut current_dir: Rc<RefCell<Directory>> = root.clone();
+fn parse_terminal_line(line: usize, text: &str) -> Result<TerminalLine, ReplayError> {
+    let parts: Vec<&str> = text.split_ascii_whitespace().collect();
+    match parts.as_slice() {
+        ["$", command, args @ ..] => {
+            let cmd = Command::parse(command).ok_or_else(|| ReplayError::UnknownCommand {
+                line,
+                command: String::from(*command),
+            })?;
+            let input = match (cmd, args) {
+                (Command::Cd, [arg]) => match Argument::parse(arg) {
+                    Argument::Name(name) => Argument::Name(check_name(line, name)?),
+                    arg => arg,
+                },
+                (Command::Cd, []) => return Err(ReplayError::MissingArgument { line }),
+                (Command::Ls, []) => return Ok(TerminalLine::Input(TerminalInput::Ls)),
+                _ => return Err(ReplayError::UnexpectedArgument { line }),
+            };
+            Ok(TerminalLine::Input(TerminalInput::Cd(input)))
+        }
+        ["dir", name] => Ok(TerminalLine::Output(TerminalOutput {
+            typ: OutputType::Dir,
+            name: check_name(line, String::from(*name))?,
+        })),
+        [size, name] => match size.parse::<usize>() {
+            Ok(size) => Ok(TerminalLine::Output(TerminalOutput {
+                typ: OutputType::Size(size),
+                name: check_name(line, String::from(*name))?,
+            })),
+            Err(_) => Err(ReplayError::MalformedLine { line }),
+        },
+        _ => Err(ReplayError::MalformedLine { line }),
+    }
+}
+
+fn parse_terminal(log: &str) -> Result<Vec<TerminalLine>, ReplayError> {
+    log.lines()
+        .enumerate()
+        .map(|(index, text)| parse_terminal_line(index + 1, text))
+        .collect()
+}
 
-    for line in lines {
+// Replay a terminal log into a filesystem. Listing a directory again does not
+// duplicate its entries, and changing into a directory that was not listed
+// yet creates it.
+fn replay(log: &str) -> Result<FileSystem, ReplayError> {
+    let lines = parse_terminal(log)?;
+
+    let mut fs = FileSystem::new();
+    let mut current_dir = FileSystem::ROOT;
+    let mut listing = false;
+
+    for (index, line) in lines.into_iter().enumerate() {
+        let line_number = index + 1;
         match line {
-            TerminalLine::Input(input) => match input.cmd {
-                Command::Cd => match input.arg {
-                    Argument::Root => current_dir = root.clone(),
-                    Argument::Parent => {
-                        let idk = current_dir
-                            .borrow_mut()
-                            .parent
-                            .as_ref()
-                            .unwrap()
-                            .upgrade()
-                            .unwrap();
-                        current_dir = idk
+            TerminalLine::Input(input) => {
+                listing = matches!(input, TerminalInput::Ls);
+                if let TerminalInput::Cd(arg) = input {
+                    current_dir = match arg {
+                        Argument::Root => FileSystem::ROOT,
+                        Argument::Parent => fs
+                            .parent(current_dir)
+                            .ok_or(ReplayError::AboveRoot { line: line_number })?,
+                        Argument::Name(name) => {
+                            if fs.get_file(current_dir, &name).is_some() {
+                                return Err(ReplayError::FileAndDir {
+                                    line: line_number,
+                                    name,
+                                });
+                            }
+                            match fs.get_dir(current_dir, &name) {
+                                Some(dir) => dir,
+                                None => fs.add_dir(current_dir, &name),
+                            }
+                        }
                     }
-                    Argument::Name(s) => {
-                        let idk = current_dir.borrow_mut().get_dir(&s).clone();
-                        current_dir = idk
-                    }
-                    _ => panic!(),
-                },
-                Command::Ls => (),
-            },
-            TerminalLine::Output(output) => match output.typ {
-                OutputType::Dir => {
-                    let mut idk = current_dir.borrow_mut();
-                    let weak = Rc::downgrade(&current_dir);
-                    let new_dir = Directory::new(&output.name, Some(weak));
-                    idk.subdirs.push(Rc::new(RefCell::new(new_dir)))
                 }
-                OutputType::Size(size) => {
-                    let mut idk = current_dir.borrow_mut();
-                    idk.files.push(SingleFile::new(&output.name, size))
+            }
+            TerminalLine::Output(output) => {
+                if !listing {
+                    return Err(ReplayError::OutputWithoutLs { line: line_number });
+                }
+                let dir = fs.get_dir(current_dir, &output.name);
+                let file = fs.get_file(current_dir, &output.name);
+                match (output.typ, dir, file) {
+                    (OutputType::Dir, None, None) => {
+                        fs.add_dir(current_dir, &output.name);
+                    }
+                    (OutputType::Size(size), None, None) => {
+                        fs.add_file(current_dir, &output.name, size)
+                    }
+                    (OutputType::Dir, Some(_), _) => (),
+                    (OutputType::Size(size), _, Some(index)) => {
+                        let old = fs.dirs[current_dir].files[index].size;
+                        if old != size {
+                            return Err(ReplayError::SizeMismatch {
+                                line: line_number,
+                                name: output.name,
+                                old,
+                                new: size,
+                            });
+                        }
+                    }
+                    _ => {
+                        return Err(ReplayError::FileAndDir {
+                            line: line_number,
+                            name: output.name,
+                        })
+                    }
                 }
-            },
+            }
         }
     }
 
-    println!("root size: {:?}", root.borrow_mut().size());
+    fs.update_sizes();
+    Ok(fs)
+}
+
+fn check_replay() -> Result<()> {
+    let error = |log: &str| replay(log).unwrap_err().to_string();
+
+    // Repeated listings and cd into unlisted directories.
+    let fs = replay("$ cd x\n$ ls\n10 a\n$ ls\n10 a\ndir y\n$ cd ..\n$ ls\ndir x\n5 b")?;
+    assert_eq!(fs.dirs.len(), 3);
+    assert_eq!(fs.size(FileSystem::ROOT), 15);
+
+    assert_eq!(error("$ cd /\n$ pwd"), "line 2: unknown command 'pwd'");
+    assert_eq!(error("$ cd"), "line 1: cd needs a directory");
+    assert_eq!(error("$ ls -l"), "line 1: unexpected argument");
+    assert_eq!(error("$ ls\n12 a b"), "line 2: malformed line");
+    assert_eq!(error("$ ls\nabc a"), "line 2: malformed line");
+    assert_eq!(
+        error("$ cd /\n10 a"),
+        "line 2: output without a preceding ls"
+    );
+    assert_eq!(error("$ cd /\n$ cd .."), "line 2: cd .. at the root");
+    assert_eq!(
+        error("$ ls\ndir a\n10 a"),
+        "line 3: 'a' is both a file and a directory"
+    );
+    assert_eq!(
+        error("$ ls\n10 a\n$ cd a"),
+        "line 3: 'a' is both a file and a directory"
+    );
+    assert_eq!(
+        error("$ ls\n10 a\n$ ls\n11 a"),
+        "line 4: 'a' was listed with size 10 before, now 11"
+    );
+    assert_eq!(
+        replay("$ ls\ndir ..").err(),
+        Some(ReplayError::InvalidName {
+            line: 2,
+            name: String::from("..")
+        })
+    );
+    assert_eq!(error("$ ls\ndir ."), "line 2: '.' is not a valid name");
+    assert_eq!(error("$ cd ."), "line 1: '.' is not a valid name");
+    assert_eq!(error("$ cd a/b"), "line 1: 'a/b' is not a valid name");
+    assert_eq!(error("$ ls\n10 x/y"), "line 2: 'x/y' is not a valid name");
+
+    Ok(())
+}
+
+fn build_filesystem(input: Input) -> Result<FileSystem> {
+    let log = match input {
+        Input::Test => read_to_string("test.txt")?,
+        Input::Puzzle => read_to_string("input.txt")?,
+    };
+    Ok(replay(&log)?)
+}
+
+fn solve(part: Part, input: Input) -> Result<usize> {
+    let fs = build_filesystem(input)?;
+
+    println!("root size: {:?}", fs.size(FileSystem::ROOT));
 
     match part {
         Part::One => {
-            let result = root.borrow_mut().sum_subdirs_with_size(100000);
+            let result = fs.sum_subdirs_with_size(FileSystem::ROOT, 100000);
             Ok(result)
         }
         Part::Two => {
-            let unused = 70_000_000 - root.borrow_mut().size();
-            println!("unused: {:?}", unused);
-            let required = 30_000_000 - unused;
-            let result = root.borrow_mut().smallest_subdir_with_size(required);
-            Ok(result)
+            let planner = CleanupPlanner {
+                capacity: 70_000_000,
+                required: 30_000_000,
+            };
+            let plan = planner.plan(&fs, PlanMode::SingleDir).unwrap();
+            println!("delete: {:?}", plan.paths(&fs));
+            Ok(plan.freed)
         }
     }
 }
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("bench") => {
+            benchmark();
+            return Ok(());
+        }
+        Some("tree") => {
+            let max_depth = args.get(2).map(|depth| depth.parse()).transpose()?;
+            print!(
+                "{}",
+                build_filesystem(Input::Puzzle)?.render_tree(max_depth)
+            );
+            return Ok(());
+        }
+        Some("find") => {
+            let fs = build_filesystem(Input::Puzzle)?;
+            for (path, _) in fs.find(args.get(2).map_or("*", |pattern| pattern.as_str())) {
+                println!("{}", path);
+            }
+            return Ok(());
+        }
+        // Plan a cleanup of the puzzle's filesystem for a disk of the given
+        // capacity, deleting a `single` directory (the default) or a `set`.
+        Some("plan") => {
+            let fs = build_filesystem(Input::Puzzle)?;
+            let planner = CleanupPlanner {
+                capacity: args.get(2).map_or(Ok(70_000_000), |arg| arg.parse())?,
+                required: args.get(3).map_or(Ok(30_000_000), |arg| arg.parse())?,
+            };
+            let mode = match args.get(4).map(|arg| arg.as_str()) {
+                None | Some("single") => PlanMode::SingleDir,
+                Some("set") => PlanMode::DirSet,
+                Some(mode) => anyhow::bail!("unknown plan mode '{}'", mode),
+            };
+            match planner.plan(&fs, mode) {
+                Some(plan) => println!("free {} by deleting {:?}", plan.freed, plan.paths(&fs)),
+                None => println!("no plan frees enough space"),
+            }
+            return Ok(());
+        }
+        // Recreate the puzzle's filesystem in a directory, by default a new
+        // one in the temp directory.
+        Some("export") => {
+            let target = match args.get(2) {
+                Some(path) => PathBuf::from(path),
+                None => temp_dir().join(format!("day07-{}", std::process::id())),
+            };
+            build_filesystem(Input::Puzzle)?.export_dir(&target)?;
+            println!("exported to {}", target.display());
+            return Ok(());
+        }
+        Some("tar") => {
+            build_filesystem(Input::Puzzle)?.export_tar(&mut io::stdout().lock())?;
+            return Ok(());
+        }
+        // Print a terminal log for a directory on disk.
+        Some("import") => {
+            let source = args.get(2).map_or(".", |path| path.as_str());
+            print!(
+                "{}",
+                FileSystem::import_dir(Path::new(source))?.transcript()
+            );
+            return Ok(());
+        }
+        // Print a random transcript, with the expected answers on stderr.
+        Some("generate") => {
+            let arg = |i: usize, default| args.get(i).map_or(Ok(default), |arg| arg.parse());
+            let config = GeneratorConfig {
+                seed: arg(2, 1)? as u64,
+                max_depth: arg(3, 4)?,
+                max_fan_out: arg(4, 4)?,
+                max_files: arg(5, 5)?,
+                max_file_size: arg(6, 300_000)?,
+            };
+            if config.max_file_size == 0 {
+                anyhow::bail!("the maximum file size must be at least 1");
+            }
+            let generated = generate(&config);
+            print!("{}", generated.transcript);
+            eprintln!("part 1: {}, part 2: {:?}", generated.part1, generated.part2);
+            return Ok(());
+        }
+        Some("du") => {
+            let max_depth = args.get(2).map(|depth| depth.parse()).transpose()?;
+            print!("{}", build_filesystem(Input::Puzzle)?.render_du(max_depth));
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let start = Instant::now();
 
+    // The tree is plain data, so it can be analysed from several threads.
+    let fs = build_filesystem(Input::Test)?;
+    let (test1, test2) = thread::scope(|scope| {
+        let part1 = scope.spawn(|| fs.sum_subdirs_with_size(FileSystem::ROOT, 100000));
+        let part2 = scope.spawn(|| fs.smallest_subdir_with_size(FileSystem::ROOT, 8381165));
+        (part1.join().unwrap(), part2.join().unwrap())
+    });
+    assert_eq!((test1, test2), (95437, 24933642));
+
+    check_rendering()?;
+    check_queries()?;
+    check_replay()?;
+    check_planner()?;
+    check_export()?;
+    check_generated()?;
+
     let test1 = solve(Part::One, Input::Test)?;
     assert_eq!(test1, 95437);
 
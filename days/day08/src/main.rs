@@ -1,7 +1,8 @@
 use anyhow::Result;
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
+use grid::{Direction, Grid, GridError, Pos};
+use rng::Rng;
+use std::fs::{read_to_string, write};
+use std::iter::once;
 use std::time::Instant;
 
 enum Part {
@@ -14,159 +15,646 @@ enum Input {
     Puzzle,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Observer {
+    pos: Pos,
+    height: u32,
+}
+
 #[derive(Debug)]
 struct TreePatch {
-    trees: Vec<Vec<u32>>,
-    height: usize,
-    width: usize,
+    trees: Grid<u32>,
 }
 
 impl TreePatch {
-    fn new(trees: Vec<Vec<u32>>) -> Self {
-        let height = trees.len();
-        let width = trees[0].len();
-        Self {
-            trees: trees,
-            height: height,
-            width: width,
-        }
+    fn new(trees: Grid<u32>) -> Self {
+        Self { trees }
     }
 
-    fn is_visible_from_left(&self, x: usize, y: usize) -> bool {
-        let tree_height = self.trees[y][x];
-        self.trees[y][0..x].iter().all(|t| t < &tree_height)
+    // One row of digits per line, all rows equally long. No lines at all
+    // make an empty patch.
+    fn parse(text: &str) -> Result<Self, GridError> {
+        Grid::parse(text, |c| c.to_digit(10)).map(TreePatch::new)
     }
 
-    fn is_visible_from_right(&self, x: usize, y: usize) -> bool {
-        let tree_height = self.trees[y][x];
-        self.trees[y][x + 1..].iter().all(|t| t < &tree_height)
+    fn count_visible(&self) -> usize {
+        self.visibility_map()
+            .iter()
+            .filter(|&&visible| visible)
+            .count()
     }
 
-    fn is_visible_from_top(&self, x: usize, y: usize) -> bool {
-        let tree_height = self.trees[y][x];
-        (0..y).all(|t| self.trees[t][x] < tree_height)
+    // Zero for an empty patch, like for a patch with only edge trees.
+    fn best_scenic_score(&self) -> usize {
+        self.scenic_scores().iter().copied().max().unwrap_or(0)
     }
 
-    fn is_visible_from_bottom(&self, x: usize, y: usize) -> bool {
-        let tree_height = self.trees[y][x];
-        (y + 1..self.height).all(|t| self.trees[t][x] < tree_height)
+    fn is_visible_from(&self, pos: Pos, direction: Direction) -> bool {
+        let tree_height = self.trees[pos];
+        self.trees
+            .ray(pos, direction)
+            .all(|(_, &t)| t < tree_height)
     }
 
-    fn viewing_distance_left(&self, x: usize, y: usize) -> usize {
-        let tree_height = self.trees[y][x];
+    fn viewing_distance_towards(&self, pos: Pos, direction: Direction) -> usize {
+        let tree_height = self.trees[pos];
         let mut result = 0;
-        for i in (0..x).rev() {
+        for (_, &t) in self.trees.ray(pos, direction) {
             result += 1;
-            if self.trees[y][i] >= tree_height {
+            if t >= tree_height {
                 break;
             }
         }
         result
     }
 
-    fn viewing_distance_right(&self, x: usize, y: usize) -> usize {
-        let tree_height = self.trees[y][x];
-        let mut result = 0;
-        for i in x + 1..self.width {
-            result += 1;
-            if self.trees[y][i] >= tree_height {
-                break;
+    fn is_visible(&self, pos: Pos) -> bool {
+        Direction::ORTHOGONAL
+            .into_iter()
+            .any(|direction| self.is_visible_from(pos, direction))
+    }
+
+    fn viewing_distance(&self, pos: Pos) -> usize {
+        Direction::ORTHOGONAL
+            .into_iter()
+            .map(|direction| self.viewing_distance_towards(pos, direction))
+            .product()
+    }
+
+    // Visibility of every tree from outside the patch, looking along the
+    // rows and columns.
+    fn visibility_map(&self) -> Grid<bool> {
+        self.visibility_map_from(&Direction::ORTHOGONAL)
+    }
+
+    // Visibility of every tree from outside the patch, for observers beyond
+    // the edge that `direction` leads to, for each of `directions`. Every line
+    // of sight is swept once from the edge inwards, keeping the tallest tree
+    // seen so far, which makes this linear in the number of trees.
+    fn visibility_map_from(&self, directions: &[Direction]) -> Grid<bool> {
+        let mut visible = self.trees.map(|_| false);
+
+        for &direction in directions {
+            let edge = self
+                .trees
+                .positions()
+                .filter(|&pos| self.trees.step(pos, direction).is_none());
+            for start in edge {
+                let line = once((start, &self.trees[start]))
+                    .chain(self.trees.ray(start, direction.opposite()));
+                let mut tallest = None;
+                for (pos, &tree_height) in line {
+                    if tallest.is_none_or(|tallest| tree_height > tallest) {
+                        visible[pos] = true;
+                        tallest = Some(tree_height);
+                    }
+                }
             }
         }
-        result
+
+        visible
     }
 
-    fn viewing_distance_top(&self, x: usize, y: usize) -> usize {
-        let tree_height = self.trees[y][x];
-        let mut result = 0;
-        for i in (0..y).rev() {
-            result += 1;
-            if self.trees[i][x] >= tree_height {
-                break;
+    // Trees seen by `observer` looking in `direction`. A tree hides the ones
+    // behind it that are no taller than itself, unless the observer is taller
+    // and looks over it. For an observer as tall as the tree it stands on,
+    // the trees up to the first one that is at least as tall are the ones
+    // counted by its viewing distance.
+    fn seen_by(&self, observer: Observer, direction: Direction) -> Vec<Pos> {
+        let mut tallest = None;
+        let mut seen = Vec::new();
+        for (pos, &tree_height) in self.trees.ray(observer.pos, direction) {
+            if tallest.is_none_or(|tallest| tallest < observer.height || tallest < tree_height) {
+                seen.push(pos);
             }
+            tallest = tallest.max(Some(tree_height));
         }
-        result
+        seen
     }
 
-    fn viewing_distance_bottom(&self, x: usize, y: usize) -> usize {
-        let tree_height = self.trees[y][x];
-        let mut result = 0;
-        for i in y + 1..self.height {
-            result += 1;
-            if self.trees[i][x] >= tree_height {
-                break;
+    // Scenic score of every tree. Each row and column is swept from both
+    // ends with a stack of the trees that still block the view, lowest on
+    // top: a new tree pops every lower tree, and the one left on top (if any)
+    // is where its view stops. Every tree is pushed and popped at most once
+    // per sweep. Columns are swept a row at a time, with a stack per column,
+    // to keep memory access sequential.
+    fn scenic_scores(&self) -> Grid<usize> {
+        let (width, height) = (self.trees.width(), self.trees.height());
+        let mut scores = self.trees.map(|_| 1);
+        let mut stack = Vec::new();
+
+        for y in 0..height {
+            let (row, scores) = (self.trees.row(y), scores.row_mut(y));
+            stack.clear();
+            for x in 0..width {
+                scores[x] *= viewing_distance_from(&mut stack, x, row[x]);
+            }
+            stack.clear();
+            for (i, x) in (0..width).rev().enumerate() {
+                scores[x] *= viewing_distance_from(&mut stack, i, row[x]);
             }
         }
-        result
+
+        let mut stacks = vec![Vec::new(); width];
+        for y in 0..height {
+            let (row, scores) = (self.trees.row(y), scores.row_mut(y));
+            for x in 0..width {
+                scores[x] *= viewing_distance_from(&mut stacks[x], y, row[x]);
+            }
+        }
+        stacks.iter_mut().for_each(Vec::clear);
+        for (i, y) in (0..height).rev().enumerate() {
+            let (row, scores) = (self.trees.row(y), scores.row_mut(y));
+            for x in 0..width {
+                scores[x] *= viewing_distance_from(&mut stacks[x], i, row[x]);
+            }
+        }
+
+        scores
     }
+}
+
+fn read_patch(input: Input) -> Result<TreePatch> {
+    let text = match input {
+        Input::Test => read_to_string("test.txt")?,
+        Input::Puzzle => read_to_string("input.txt")?,
+    };
+    Ok(TreePatch::parse(&text)?)
+}
+
+fn check_parsing() {
+    assert_eq!(
+        TreePatch::parse("123\n45\n678").unwrap_err(),
+        GridError::Ragged {
+            line: 2,
+            len: 2,
+            width: 3
+        }
+    );
+    assert_eq!(
+        TreePatch::parse("123\n4 6").unwrap_err(),
+        GridError::BadCell {
+            line: 2,
+            column: 2,
+            cell: ' '
+        }
+    );
+    assert_eq!(
+        TreePatch::parse("12a").unwrap_err().to_string(),
+        "line 1, column 3: unexpected 'a'"
+    );
+    assert!(TreePatch::parse("12\r\n34\r\n").is_ok());
+}
+
+// Neighbours come in the order of `Direction::ORTHOGONAL` and
+// `Direction::ALL`, skipping the ones outside the grid.
+fn check_grid() {
+    let digits = |text| Grid::parse(text, |c| c.to_digit(10)).unwrap();
+    let square = digits("123\n456\n789");
+    let pos = |x, y| Pos::new(x, y);
+
+    assert_eq!(
+        Grid::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]),
+        Ok(square.clone())
+    );
+    assert_eq!(
+        Grid::from_rows(vec![vec![1, 2], vec![3]]),
+        Err(GridError::Ragged {
+            line: 2,
+            len: 1,
+            width: 2
+        })
+    );
+    assert!(Grid::<u32>::from_rows(vec![]).unwrap().is_empty());
+
+    assert_eq!(square.column(1).collect::<Vec<_>>(), [&2, &5, &8]);
+    assert_eq!(square.column(2).rev().collect::<Vec<_>>(), [&9, &6, &3]);
+    assert_eq!(digits("7").column(0).rev().collect::<Vec<_>>(), [&7]);
+
+    let neighbours4 = |grid: &Grid<u32>, p| grid.neighbours4(p).collect::<Vec<_>>();
+    let neighbours8 = |grid: &Grid<u32>, p| grid.neighbours8(p).collect::<Vec<_>>();
 
-    fn is_visible(&self, x: usize, y: usize) -> bool {
-        self.is_visible_from_left(x, y)
-            || self.is_visible_from_right(x, y)
-            || self.is_visible_from_top(x, y)
-            || self.is_visible_from_bottom(x, y)
+    // Corners, edges and the middle of a 3x3 grid.
+    assert_eq!(neighbours4(&square, pos(0, 0)), [pos(1, 0), pos(0, 1)]);
+    assert_eq!(neighbours4(&square, pos(2, 2)), [pos(1, 2), pos(2, 1)]);
+    assert_eq!(
+        neighbours4(&square, pos(1, 0)),
+        [pos(0, 0), pos(2, 0), pos(1, 1)]
+    );
+    assert_eq!(
+        neighbours4(&square, pos(1, 1)),
+        [pos(0, 1), pos(2, 1), pos(1, 0), pos(1, 2)]
+    );
+    assert_eq!(
+        neighbours8(&square, pos(2, 2)),
+        [pos(1, 2), pos(2, 1), pos(1, 1)]
+    );
+    assert_eq!(
+        neighbours8(&square, pos(0, 1)),
+        [pos(1, 1), pos(0, 0), pos(0, 2), pos(1, 0), pos(1, 2)]
+    );
+    assert_eq!(neighbours8(&square, pos(1, 1)).len(), 8);
+
+    // Single rows and columns only have neighbours along their length.
+    let row = digits("12345");
+    assert_eq!(neighbours4(&row, pos(0, 0)), [pos(1, 0)]);
+    assert_eq!(neighbours4(&row, pos(2, 0)), [pos(1, 0), pos(3, 0)]);
+    assert_eq!(neighbours8(&row, pos(2, 0)), [pos(1, 0), pos(3, 0)]);
+    assert_eq!(neighbours8(&row, pos(4, 0)), [pos(3, 0)]);
+    let column = digits("1\n2\n3");
+    assert_eq!(neighbours4(&column, pos(0, 1)), [pos(0, 0), pos(0, 2)]);
+    assert_eq!(neighbours8(&column, pos(0, 2)), [pos(0, 1)]);
+    assert!(neighbours8(&digits("7"), pos(0, 0)).is_empty());
+}
+
+// Patches too small to have an inside: every tree is on the edge, so all of
+// them are visible and none has a view in every direction.
+fn check_degenerate_patches() {
+    for (text, trees) in [
+        ("", 0),
+        ("7", 1),
+        ("31415", 5),
+        ("3\n1\n4", 3),
+        ("11\n11", 4),
+    ] {
+        let patch = TreePatch::parse(text).unwrap();
+        assert_eq!(patch.count_visible(), trees, "{:?}", text);
+        assert_eq!(patch.best_scenic_score(), 0, "{:?}", text);
+        assert_eq!(
+            patch
+                .visibility_map_from(&Direction::ALL)
+                .iter()
+                .filter(|&&v| v)
+                .count(),
+            trees
+        );
+        assert_eq!(
+            render_visibility(&patch.visibility_map())
+                .matches('#')
+                .count(),
+            trees
+        );
     }
+}
 
-    fn viewing_distance(&self, x: usize, y: usize) -> usize {
-        let result = self.viewing_distance_left(x, y)
-            * self.viewing_distance_right(x, y)
-            * self.viewing_distance_top(x, y)
-            * self.viewing_distance_bottom(x, y);
-        result
+// The sweeps must agree with checking every tree against its whole row and
+// column.
+fn check_visibility_map(input: Input) -> Result<()> {
+    let patch = read_patch(input)?;
+    let visible = patch.visibility_map();
+    for pos in patch.trees.positions() {
+        assert_eq!(visible[pos], patch.is_visible(pos), "tree {:?}", pos);
     }
+    Ok(())
 }
 
-fn solve(part: Part, input: Input) -> Result<usize> {
-    let file = match input {
-        Input::Test => File::open("test.txt")?,
-        Input::Puzzle => File::open("input.txt")?,
+const DIAGONALS: [Direction; 4] = [
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
+fn parse_direction(s: &str) -> Option<Direction> {
+    match s {
+        "up" => Some(Direction::Up),
+        "down" => Some(Direction::Down),
+        "left" => Some(Direction::Left),
+        "right" => Some(Direction::Right),
+        "up-left" => Some(Direction::UpLeft),
+        "up-right" => Some(Direction::UpRight),
+        "down-left" => Some(Direction::DownLeft),
+        "down-right" => Some(Direction::DownRight),
+        _ => None,
+    }
+}
+
+// Diagonal sightlines must agree with checking every tree against the trees
+// between it and each corner.
+fn check_diagonal_visibility(input: Input) -> Result<()> {
+    let patch = read_patch(input)?;
+    let visible = patch.visibility_map_from(&DIAGONALS);
+    for pos in patch.trees.positions() {
+        let expected = DIAGONALS
+            .into_iter()
+            .any(|direction| patch.is_visible_from(pos, direction));
+        assert_eq!(visible[pos], expected, "tree {:?}", pos);
+    }
+    Ok(())
+}
+
+fn check_observers() -> Result<()> {
+    let patch = read_patch(Input::Test)?;
+    let seen = |x, y, height, direction| {
+        let observer = Observer {
+            pos: Pos::new(x, y),
+            height,
+        };
+        patch
+            .seen_by(observer, direction)
+            .into_iter()
+            .map(|pos| (pos.x, pos.y))
+            .collect::<Vec<_>>()
     };
-    let reader = BufReader::new(file);
-    let trees: Vec<Vec<u32>> = reader
-        .lines()
-        .map(|line| line.unwrap())
-        .map(|line| {
-            line.chars()
-                .map(|c| c.to_digit(10).unwrap())
-                .collect::<Vec<u32>>()
-        })
-        .collect();
 
-    let patch = TreePatch::new(trees);
+    // From the best treehouse spot, as in part 2.
+    assert_eq!(seen(2, 3, 5, Direction::Up), vec![(2, 2), (2, 1)]);
+    assert_eq!(seen(2, 3, 5, Direction::Left), vec![(1, 3), (0, 3)]);
+    assert_eq!(seen(2, 3, 5, Direction::UpLeft), vec![(1, 2)]);
+    // A tall observer sees over everything, a short one only over the trees
+    // that are shorter than what lies behind them.
+    assert_eq!(
+        seen(0, 0, 9, Direction::DownRight),
+        vec![(1, 1), (2, 2), (3, 3), (4, 4)]
+    );
+    assert_eq!(seen(0, 2, 0, Direction::Right), vec![(1, 2)]);
+    assert_eq!(seen(4, 4, 0, Direction::UpLeft), vec![(3, 3), (1, 1)]);
+    assert_eq!(seen(4, 0, 0, Direction::Right), vec![]);
+
+    let count = |visible: Grid<bool>| visible.iter().filter(|&&v| v).count();
+    assert_eq!(count(patch.visibility_map_from(&DIAGONALS)), 22);
+    assert_eq!(count(patch.visibility_map_from(&Direction::ALL)), 22);
+
+    Ok(())
+}
+
+// Viewing distance of the tree at position `i` of a sweep, given the stack of
+// `(position, height)` of the trees before it that may block the view.
+fn viewing_distance_from(blocking: &mut Vec<(usize, u32)>, i: usize, tree_height: u32) -> usize {
+    while blocking.last().is_some_and(|&(_, h)| h < tree_height) {
+        blocking.pop();
+    }
+    let distance = match blocking.last() {
+        Some(&(j, _)) => i - j,
+        None => i,
+    };
+    blocking.push((i, tree_height));
+    distance
+}
+
+// The stacks must agree with walking outward from every tree.
+fn check_scenic_scores(input: Input) -> Result<()> {
+    let patch = read_patch(input)?;
+    let scores = patch.scenic_scores();
+    for pos in patch.trees.positions() {
+        assert_eq!(scores[pos], patch.viewing_distance(pos), "tree {:?}", pos);
+    }
+    Ok(())
+}
+
+// A square forest of random heights. In a sparse forest most trees have
+// height 0, so the few others can see far.
+fn generate_forest(size: usize, sparse: bool, rng: &mut Rng) -> TreePatch {
+    let trees = Grid::new(size, size, 0).map(|_| match sparse {
+        true if !rng.next_u64().is_multiple_of(100) => 0,
+        _ => (rng.next_u64() % 10) as u32,
+    });
+    TreePatch::new(trees)
+}
+
+// Compare walking outward from every tree against the sweeps on generated
+// 1000x1000 forests. With only ten heights a walk stops at the next tree at
+// least as tall, so walking is never far off linear in practice and the
+// stacks mostly pay off as a bound. Run with `cargo run --release -- bench`.
+fn benchmark() {
+    let mut rng = Rng::new(0x5eed);
+
+    for sparse in [false, true] {
+        let patch = generate_forest(1000, sparse, &mut rng);
+        println!("{} forest:", if sparse { "sparse" } else { "dense" });
+
+        let start = Instant::now();
+        let visible = patch
+            .trees
+            .positions()
+            .filter(|&pos| patch.is_visible(pos))
+            .count();
+        let naive_time = start.elapsed().as_micros();
+        let start = Instant::now();
+        let swept = patch.visibility_map().iter().filter(|&&v| v).count();
+        let sweep_time = start.elapsed().as_micros();
+        assert_eq!(visible, swept);
+        println!(
+            "  visible {:>7}: per tree {:>8} us, sweeps {:>6} us",
+            visible, naive_time, sweep_time
+        );
+
+        let start = Instant::now();
+        let best = patch
+            .trees
+            .positions()
+            .map(|pos| patch.viewing_distance(pos))
+            .max();
+        let naive_time = start.elapsed().as_micros();
+        let start = Instant::now();
+        let stacked = patch.scenic_scores().iter().copied().max();
+        let stack_time = start.elapsed().as_micros();
+        assert_eq!(best, stacked);
+        println!(
+            "  best score {:>9}: per tree {:>8} us, stacks {:>6} us",
+            best.unwrap(),
+            naive_time,
+            stack_time
+        );
+    }
+}
+
+// One character per tree: `#` if it can be seen from outside the patch,
+// `.` if not.
+fn render_visibility(visible: &Grid<bool>) -> String {
+    visible
+        .map(|&visible| if visible { '#' } else { '.' })
+        .to_string()
+}
+
+// Colour for a scenic score, from dark blue through green and yellow to red.
+// Scores span several orders of magnitude, so they are placed on a log
+// scale relative to the best one.
+fn heat_colour(score: usize, max_score: usize) -> (u8, u8, u8) {
+    const STOPS: [(f64, f64, f64); 4] = [
+        (0.0, 0.0, 80.0),
+        (0.0, 160.0, 80.0),
+        (240.0, 220.0, 0.0),
+        (220.0, 0.0, 0.0),
+    ];
+
+    let t = match max_score {
+        0 => 0.0,
+        _ => (score as f64).ln_1p() / (max_score as f64).ln_1p(),
+    };
+    let scaled = t * (STOPS.len() - 1) as f64;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    let f = scaled - i as f64;
+    let mix = |a: f64, b: f64| (a + (b - a) * f).round() as u8;
+    let (from, to) = (STOPS[i], STOPS[i + 1]);
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// The patch with every tree's height drawn on its scenic score's colour,
+// using 24-bit ANSI escape codes.
+fn render_heatmap_ansi(patch: &TreePatch, scores: &Grid<usize>) -> String {
+    let max_score = scores.iter().copied().max().unwrap_or(0);
+    let mut out = String::new();
+    for y in 0..scores.height() {
+        for (x, &score) in scores.row(y).iter().enumerate() {
+            let (r, g, b) = heat_colour(score, max_score);
+            out.push_str(&format!(
+                "\x1b[48;2;{};{};{}m{}",
+                r,
+                g,
+                b,
+                patch.trees[Pos::new(x, y)]
+            ));
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+// Binary PPM image of the scenic scores, each tree drawn as a `scale` by
+// `scale` square of pixels.
+fn render_heatmap_ppm(scores: &Grid<usize>, scale: usize) -> Vec<u8> {
+    let max_score = scores.iter().copied().max().unwrap_or(0);
+    let mut out = format!(
+        "P6\n{} {}\n255\n",
+        scores.width() * scale,
+        scores.height() * scale
+    )
+    .into_bytes();
+    for row in scores.rows() {
+        for _ in 0..scale {
+            for &score in row {
+                let (r, g, b) = heat_colour(score, max_score);
+                for _ in 0..scale {
+                    out.extend([r, g, b]);
+                }
+            }
+        }
+    }
+    out
+}
 
-    let num_outer = 2 * patch.width + 2 * patch.height - 4;
+fn check_rendering() -> Result<()> {
+    let patch = read_patch(Input::Test)?;
+
+    assert_eq!(
+        render_visibility(&patch.visibility_map()),
+        "#####\n###.#\n##.##\n#.#.#\n#####\n"
+    );
+
+    assert_eq!(heat_colour(0, 8), (0, 0, 80));
+    assert_eq!(heat_colour(8, 8), (220, 0, 0));
+    assert_eq!(heat_colour(0, 0), (0, 0, 80));
+
+    let scores = patch.scenic_scores();
+    let ansi = render_heatmap_ansi(&patch, &scores);
+    assert_eq!(ansi.lines().count(), 5);
+    assert!(ansi.starts_with("\x1b[48;2;0;0;80m3"));
+    assert!(ansi.contains("\x1b[48;2;220;0;0m5"));
+
+    let header = b"P6\n10 10\n255\n";
+    let ppm = render_heatmap_ppm(&scores, 2);
+    assert_eq!(&ppm[..header.len()], header);
+    assert_eq!(ppm.len(), header.len() + 10 * 10 * 3);
+    // The best spot, (2, 3), covers pixels (4..6, 6..8).
+    let pixel = header.len() + (7 * 10 + 5) * 3;
+    assert_eq!(ppm[pixel..pixel + 3], [220, 0, 0]);
+
+    Ok(())
+}
+
+fn solve(part: Part, input: Input) -> Result<usize> {
+    let patch = read_patch(input)?;
 
     let result = match part {
-        Part::One => {
-            (1..patch.width - 1)
-                .map(|x| {
-                    (1..patch.height - 1)
-                        .map(|y| match patch.is_visible(x, y) {
-                            true => 1,
-                            false => 0,
-                        })
-                        .sum::<usize>()
-                })
-                .sum::<usize>()
-                + num_outer
-        }
-        Part::Two => (0..patch.width)
-            .map(|x| {
-                (0..patch.height)
-                    .map(|y| patch.viewing_distance(x, y))
-                    .max()
-                    .unwrap()
-            })
-            .max()
-            .unwrap(),
+        Part::One => patch.count_visible(),
+        Part::Two => patch.best_scenic_score(),
     };
 
     Ok(result)
 }
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("bench") => {
+            benchmark();
+            return Ok(());
+        }
+        // Show which trees of the puzzle can be seen from outside.
+        Some("visible") => {
+            let patch = read_patch(Input::Puzzle)?;
+            print!("{}", render_visibility(&patch.visibility_map()));
+            return Ok(());
+        }
+        // Show the puzzle's scenic scores as a heatmap in the terminal.
+        Some("heatmap") => {
+            let patch = read_patch(Input::Puzzle)?;
+            print!("{}", render_heatmap_ansi(&patch, &patch.scenic_scores()));
+            return Ok(());
+        }
+        // Write the heatmap to an image, by default `heatmap.ppm` with 4x4
+        // pixels per tree.
+        Some("ppm") => {
+            let patch = read_patch(Input::Puzzle)?;
+            let path = args.get(2).map_or("heatmap.ppm", |arg| arg.as_str());
+            let scale = args.get(3).map_or(Ok(4), |arg| arg.parse())?;
+            write(path, render_heatmap_ppm(&patch.scenic_scores(), scale))?;
+            println!("wrote {}", path);
+            return Ok(());
+        }
+        // List the trees an observer in the puzzle's patch sees, e.g.
+        // `observe 10 20 5 up-left`.
+        Some("observe") => {
+            let patch = read_patch(Input::Puzzle)?;
+            let arg = |i: usize| args.get(i).map_or("", |arg| arg.as_str());
+            let observer = Observer {
+                pos: Pos::new(arg(2).parse()?, arg(3).parse()?),
+                height: arg(4).parse()?,
+            };
+            if !patch.trees.contains(observer.pos) {
+                anyhow::bail!("{:?} is outside the patch", observer.pos);
+            }
+            let direction = parse_direction(arg(5))
+                .ok_or_else(|| anyhow::anyhow!("unknown direction {:?}", arg(5)))?;
+            for pos in patch.seen_by(observer, direction) {
+                println!("({}, {}): {}", pos.x, pos.y, patch.trees[pos]);
+            }
+            return Ok(());
+        }
+        // Count the puzzle's trees that can be seen from outside along the
+        // diagonals, and along any of the eight directions.
+        Some("diagonals") => {
+            let patch = read_patch(Input::Puzzle)?;
+            let count = |visible: Grid<bool>| visible.iter().filter(|&&v| v).count();
+            println!(
+                "diagonals: {}",
+                count(patch.visibility_map_from(&DIAGONALS))
+            );
+            println!(
+                "all directions: {}",
+                count(patch.visibility_map_from(&Direction::ALL))
+            );
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let start = Instant::now();
 
+    check_parsing();
+    check_grid();
+    check_degenerate_patches();
+
+    check_visibility_map(Input::Test)?;
+    check_visibility_map(Input::Puzzle)?;
+    check_scenic_scores(Input::Test)?;
+    check_scenic_scores(Input::Puzzle)?;
+    check_rendering()?;
+    check_diagonal_visibility(Input::Test)?;
+    check_diagonal_visibility(Input::Puzzle)?;
+    check_observers()?;
+
     let test1 = solve(Part::One, Input::Test)?;
     assert_eq!(test1, 21);
 
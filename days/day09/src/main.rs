@@ -1,5 +1,6 @@
 use anyhow::Result;
 use itertools::Itertools;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
@@ -29,20 +30,34 @@ enum CatchUpMovement {
 
 #[derive(Debug, Clone)]
 struct Knot {
-    x: usize,
-    y: usize,
+    x: i32,
+    y: i32,
 }
 
 #[derive(Debug)]
 struct Grid {
     knots: Vec<Knot>,
-    visited: Vec<Vec<bool>>,
+    // Positions each knot has been at, so memory grows with the ground the
+    // rope covers rather than with the length of the moves.
+    visited: Vec<HashSet<(i32, i32)>>,
 }
 
 impl Grid {
+    // A rope of `num_knots` knots, all at the origin. The first knot is the
+    // head.
+    fn new(num_knots: usize) -> Self {
+        assert!(num_knots > 0, "a rope needs at least one knot");
+        let mut grid = Grid {
+            knots: vec![Knot { x: 0, y: 0 }; num_knots],
+            visited: vec![HashSet::new(); num_knots],
+        };
+        grid.mark_visited();
+        grid
+    }
+
     fn get_movement(&self, knot0: usize, knot1: usize) -> CatchUpMovement {
-        let delta_x = self.knots[knot0].x as i32 - self.knots[knot1].x as i32;
-        let delta_y = self.knots[knot0].y as i32 - self.knots[knot1].y as i32;
+        let delta_x = self.knots[knot0].x - self.knots[knot1].x;
+        let delta_y = self.knots[knot0].y - self.knots[knot1].y;
 
         match (delta_x, delta_y) {
             (-2, 2) | (-2, 1) | (-1, 2) => CatchUpMovement::UpLeft,
@@ -121,15 +136,17 @@ impl Grid {
     }
 
     fn mark_visited(&mut self) {
-        let tail = self.knots.last().unwrap();
-        self.visited[tail.y][tail.x] = true;
+        for (knot, visited) in self.knots.iter().zip(self.visited.iter_mut()) {
+            visited.insert((knot.x, knot.y));
+        }
     }
 
-    fn count_visited(&self) -> usize {
-        self.visited
-            .iter()
-            .map(|row| row.iter().filter(|b| **b).count())
-            .sum()
+    fn count_visited(&self, knot: usize) -> usize {
+        self.visited[knot].len()
+    }
+
+    fn tail(&self) -> usize {
+        self.knots.len() - 1
     }
 }
 
@@ -140,13 +157,8 @@ enum Instruction {
     Down(usize),
 }
 
-fn solve(part: Part, input: Input) -> Result<usize> {
-    let file = match input {
-        Input::Test => File::open("test.txt")?,
-        Input::Puzzle => File::open("input.txt")?,
-    };
-    let reader = BufReader::new(file);
-    let instructions: Vec<Instruction> = reader
+fn parse_instructions(reader: impl BufRead) -> Vec<Instruction> {
+    reader
         .lines()
         .map(|line| line.unwrap())
         .map(|line| {
@@ -159,79 +171,109 @@ fn solve(part: Part, input: Input) -> Result<usize> {
                 _ => panic!(),
             }
         })
-        .collect();
-
-    let max_dist_left: usize = instructions
-        .iter()
-        .map(|instr| match instr {
-            Instruction::Left(n) => *n,
-            _ => 0,
-        })
-        .sum();
-    let max_dist_right: usize = instructions
-        .iter()
-        .map(|instr| match instr {
-            Instruction::Right(n) => *n,
-            _ => 0,
-        })
-        .sum();
-    let max_dist_up: usize = instructions
-        .iter()
-        .map(|instr| match instr {
-            Instruction::Up(n) => *n,
-            _ => 0,
-        })
-        .sum();
-    let max_dist_down: usize = instructions
-        .iter()
-        .map(|instr| match instr {
-            Instruction::Down(n) => *n,
-            _ => 0,
-        })
-        .sum();
+        .collect()
+}
 
-    let width = max_dist_left + max_dist_right + 1;
-    let height = max_dist_up + max_dist_down + 1;
-    let start_x = max_dist_left;
-    let start_y = max_dist_down;
+fn simulate(instructions: Vec<Instruction>, num_knots: usize) -> Grid {
+    let mut grid = Grid::new(num_knots);
+    for instr in instructions {
+        grid.execute_instruction(instr);
+    }
+    grid
+}
 
-    let visited = vec![vec![false; width]; height];
+fn count_tail_positions(instructions: Vec<Instruction>, num_knots: usize) -> usize {
+    let grid = simulate(instructions, num_knots);
+    grid.count_visited(grid.tail())
+}
 
-    let head = Knot {
-        x: start_x,
-        y: start_y,
-    };
-    let tail = Knot {
-        x: start_x,
-        y: start_y,
-    };
+fn check_unbounded_moves() {
+    let larger_example = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n";
+    assert_eq!(
+        count_tail_positions(parse_instructions(larger_example.as_bytes()), 10),
+        36
+    );
 
-    let knots = match part {
-        Part::One => vec![head, tail],
-        Part::Two => {
-            let mut knots = Vec::new();
-            knots.push(head);
-            for _ in 0..9 {
-                knots.push(tail.clone());
-            }
-            knots
-        }
-    };
+    // Far into negative coordinates: the tail follows the head along the
+    // row, then cuts the corner once the head turns down.
+    let long_moves = "L 100000\nD 3\n";
+    assert_eq!(
+        count_tail_positions(parse_instructions(long_moves.as_bytes()), 2),
+        100002
+    );
+}
 
-    let mut grid = Grid { knots, visited };
+// Each knot only follows the one before it, so knot `i` of a long rope moves
+// exactly like the tail of a rope of `i + 1` knots.
+fn check_per_knot_tracking() -> Result<()> {
+    let file = File::open("test.txt")?;
+    let grid = simulate(parse_instructions(BufReader::new(file)), 10);
+    assert_eq!(grid.count_visited(1), 13);
+    assert_eq!(grid.count_visited(grid.tail()), 1);
 
-    for instr in instructions {
-        grid.execute_instruction(instr);
+    let larger_example = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n";
+    let instructions = || parse_instructions(larger_example.as_bytes());
+    let grid = simulate(instructions(), 10);
+    for knot in 0..10 {
+        assert_eq!(
+            grid.count_visited(knot),
+            count_tail_positions(instructions(), knot + 1)
+        );
     }
+    assert_eq!(grid.count_visited(9), 36);
 
-    let result = grid.count_visited();
+    Ok(())
+}
 
-    Ok(result)
+fn solve(part: Part, input: Input) -> Result<usize> {
+    let file = match input {
+        Input::Test => File::open("test.txt")?,
+        Input::Puzzle => File::open("input.txt")?,
+    };
+    let reader = BufReader::new(file);
+    let instructions = parse_instructions(reader);
+
+    let num_knots = match part {
+        Part::One => 2,
+        Part::Two => 10,
+    };
+
+    Ok(count_tail_positions(instructions, num_knots))
 }
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    // Simulate the puzzle with a rope of the given number of knots, and print
+    // how many positions each knot, or only the given one, visited.
+    if let Some("knots") = args.get(1).map(|arg| arg.as_str()) {
+        let num_knots: usize = args.get(2).map_or(Ok(10), |arg| arg.parse())?;
+        if num_knots == 0 {
+            anyhow::bail!("a rope needs at least one knot");
+        }
+        let file = File::open("input.txt")?;
+        let grid = simulate(parse_instructions(BufReader::new(file)), num_knots);
+        match args.get(3) {
+            Some(knot) => {
+                let knot: usize = knot.parse()?;
+                if knot >= num_knots {
+                    anyhow::bail!("knot {} is not on a rope of {} knots", knot, num_knots);
+                }
+                println!("{}", grid.count_visited(knot));
+            }
+            None => {
+                for knot in 0..num_knots {
+                    println!("knot {}: {}", knot, grid.count_visited(knot));
+                }
+            }
+        }
+        return Ok(());
+    }
+
     let start = Instant::now();
 
+    check_unbounded_moves();
+    check_per_knot_tracking()?;
+
     let test1 = solve(Part::One, Input::Test)?;
     assert_eq!(test1, 13);
 
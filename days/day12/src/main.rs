@@ -1,16 +1,12 @@
 use anyhow::Result;
-use itertools::Itertools;
+use grid::{Grid, Pos};
 
 #[macro_use]
 extern crate log;
 use simplelog::{Config, LevelFilter, WriteLogger};
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::fmt;
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::path;
+use std::fs::{read_to_string, File};
 use std::time::Instant;
 
 #[derive(Clone)]
@@ -24,78 +20,23 @@ enum Input {
     Puzzle,
 }
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
-struct Location {
-    x: usize,
-    y: usize,
-}
-
 #[derive(Debug)]
-struct Grid {
-    squares: Vec<Vec<u8>>,
-    end_point: Location,
+struct HeightMap {
+    squares: Grid<u8>,
+    end_point: Pos,
 }
 
-impl Grid {
-    fn width(&self) -> usize {
-        self.squares[0].len()
-    }
-
-    fn height(&self) -> usize {
-        self.squares.len()
-    }
-
-    fn get_height(&self, loc: Location) -> u8 {
-        self.squares[loc.y][loc.x]
-    }
-
-    fn step_right(&self, loc: Location) -> Option<Location> {
-        if loc.x < self.width() - 1 {
-            Some(Location {
-                x: loc.x + 1,
-                y: loc.y,
-            })
-        } else {
-            None
-        }
-    }
-    fn step_left(&self, loc: Location) -> Option<Location> {
-        if loc.x > 0 {
-            Some(Location {
-                x: loc.x - 1,
-                y: loc.y,
-            })
-        } else {
-            None
-        }
-    }
-    fn step_up(&self, loc: Location) -> Option<Location> {
-        if loc.y > 0 {
-            Some(Location {
-                x: loc.x,
-                y: loc.y - 1,
-            })
-        } else {
-            None
-        }
-    }
-    fn step_down(&self, loc: Location) -> Option<Location> {
-        if loc.y < self.height() - 1 {
-            Some(Location {
-                x: loc.x,
-                y: loc.y + 1,
-            })
-        } else {
-            None
-        }
+impl HeightMap {
+    fn get_height(&self, loc: Pos) -> u8 {
+        self.squares[loc]
     }
 }
 
 fn search(
-    grid: &Grid,
-    current_loc: Location,
-    solutions: &mut HashMap<Location, usize>,
-    visited_locs: &mut HashSet<Location>,
+    grid: &HeightMap,
+    current_loc: Pos,
+    solutions: &mut HashMap<Pos, usize>,
+    visited_locs: &mut HashSet<Pos>,
 ) -> Option<usize> {
     debug!("searching from {:?}", current_loc);
 
@@ -113,115 +54,32 @@ fn search(
         debug!("inserting visited loc {:?}", current_loc);
         visited_locs.insert(current_loc);
 
-        let left_blocked = match grid.step_left(current_loc) {
-            Some(loc) => visited_locs.contains(&loc),
-            _ => true,
-        };
-        let right_blocked = match grid.step_right(current_loc) {
-            Some(loc) => visited_locs.contains(&loc),
-            _ => true,
-        };
-        let up_blocked = match grid.step_up(current_loc) {
-            Some(loc) => visited_locs.contains(&loc),
-            _ => true,
-        };
-        let down_blocked = match grid.step_down(current_loc) {
-            Some(loc) => visited_locs.contains(&loc),
-            _ => true,
-        };
-
-        let mut path_blocked = false;
-
-        if left_blocked && right_blocked && up_blocked && down_blocked {
-            debug!("path blocked for {:?}, backtracking", current_loc);
-            path_blocked = true;
-        }
+        let path_blocked = grid
+            .squares
+            .neighbours4(current_loc)
+            .all(|loc| visited_locs.contains(&loc));
 
         if path_blocked {
+            debug!("path blocked for {:?}, backtracking", current_loc);
             return None;
         }
 
-        if let Some(loc) = grid.step_left(current_loc) {
-            debug!("current: {:?}, trying left", current_loc);
-            if visited_locs.contains(&loc) {
-                debug!("skipping left since already visited");
-            // } else if grid.get_height(loc) <= current_height + 1 {
-            } else if grid.get_height(loc) >= current_height - 1 {
-                match search(grid, loc, solutions, visited_locs) {
-                    Some(sol) => {
-                        if 1 + sol < solution {
-                            debug!(
-                                "updating solution for {:?} from left: {}",
-                                current_loc,
-                                1 + sol
-                            );
-                            solution = 1 + sol;
-                        }
-                    }
-                    _ => (),
-                }
-            }
-        }
-        if let Some(loc) = grid.step_right(current_loc) {
-            debug!("current: {:?}, trying right", current_loc);
+        for loc in grid.squares.neighbours4(current_loc) {
+            debug!("current: {:?}, trying {:?}", current_loc, loc);
             if visited_locs.contains(&loc) {
-                debug!("skipping right since already visited");
+                debug!("skipping {:?} since already visited", loc);
             // } else if grid.get_height(loc) <= current_height + 1 {
             } else if grid.get_height(loc) >= current_height - 1 {
-                match search(grid, loc, solutions, visited_locs) {
-                    Some(sol) => {
-                        if 1 + sol < solution {
-                            debug!(
-                                "updating solution for {:?} from left: {}",
-                                current_loc,
-                                1 + sol
-                            );
-                            solution = 1 + sol;
-                        }
+                if let Some(sol) = search(grid, loc, solutions, visited_locs) {
+                    if 1 + sol < solution {
+                        debug!(
+                            "updating solution for {:?} from {:?}: {}",
+                            current_loc,
+                            loc,
+                            1 + sol
+                        );
+                        solution = 1 + sol;
                     }
-                    _ => (),
-                }
-            }
-        }
-        if let Some(loc) = grid.step_up(current_loc) {
-            debug!("current: {:?}, trying up", current_loc);
-            if visited_locs.contains(&loc) {
-                debug!("skipping up since already visited");
-            // } else if grid.get_height(loc) <= current_height + 1 {
-            } else if grid.get_height(loc) >= current_height - 1 {
-                match search(grid, loc, solutions, visited_locs) {
-                    Some(sol) => {
-                        if 1 + sol < solution {
-                            debug!(
-                                "updating solution for {:?} from left: {}",
-                                current_loc,
-                                1 + sol
-                            );
-                            solution = 1 + sol;
-                        }
-                    }
-                    _ => (),
-                }
-            }
-        }
-        if let Some(loc) = grid.step_down(current_loc) {
-            debug!("current: {:?}, trying down", current_loc);
-            if visited_locs.contains(&loc) {
-                debug!("skipping down since already visited");
-            // } else if grid.get_height(loc) <= current_height + 1 {
-            } else if grid.get_height(loc) >= current_height - 1 {
-                match search(grid, loc, solutions, visited_locs) {
-                    Some(sol) => {
-                        if 1 + sol < solution {
-                            debug!(
-                                "updating solution for {:?} from left: {}",
-                                current_loc,
-                                1 + sol
-                            );
-                            solution = 1 + sol;
-                        }
-                    }
-                    _ => (),
                 }
             }
         }
@@ -240,47 +98,37 @@ fn search(
 }
 
 fn solve(part: Part, input: Input) -> Result<usize> {
-    let file = match input {
-        Input::Test => File::open("test.txt")?,
-        Input::Puzzle => File::open("input.txt")?,
+    let text = match input {
+        Input::Test => read_to_string("test.txt")?,
+        Input::Puzzle => read_to_string("input.txt")?,
     };
-    let reader = BufReader::new(file);
 
-    let squares: Vec<Vec<u8>> = reader
-        .lines()
-        .map(|line| line.unwrap())
-        .map(|line| {
-            line.chars()
-                .map(|c| match c {
-                    'S' => 27,
-                    'E' => 28,
-                    _ => c as u8 - 96,
-                })
-                .collect::<Vec<u8>>()
-        })
-        .collect();
+    let squares = Grid::parse(&text, |c| match c {
+        'S' => Some(27),
+        'E' => Some(28),
+        'a'..='z' => Some(c as u8 - 96),
+        _ => None,
+    })?;
 
-    let mut grid = Grid {
+    let mut grid = HeightMap {
         squares,
-        end_point: Location { x: 0, y: 0 },
+        end_point: Pos { x: 0, y: 0 },
     };
     // println!("{:?}", grid);
 
-    let mut start = Location { x: 0, y: 0 };
-    let mut end = Location { x: 0, y: 0 };
+    let mut start = Pos { x: 0, y: 0 };
+    let mut end = Pos { x: 0, y: 0 };
 
-    for i in 0..grid.width() {
-        for j in 0..grid.height() {
-            if grid.get_height(Location { x: i, y: j }) == 27 {
-                start = Location { x: i, y: j };
-            } else if grid.get_height(Location { x: i, y: j }) == 28 {
-                end = Location { x: i, y: j };
-            }
+    for loc in grid.squares.positions() {
+        if grid.get_height(loc) == 27 {
+            start = loc;
+        } else if grid.get_height(loc) == 28 {
+            end = loc;
         }
     }
 
-    grid.squares[start.y][start.x] = 1;
-    grid.squares[end.y][end.x] = 26;
+    grid.squares[start] = 1;
+    grid.squares[end] = 26;
     grid.end_point = end;
 
     println!("start: {:?}", start);
@@ -0,0 +1,316 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Pos {
+    pub fn new(x: usize, y: usize) -> Self {
+        Pos { x, y }
+    }
+}
+
+// Directions in screen coordinates, so `Up` is towards row 0.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    // Day 12's search tries directions in this order, and the order of its
+    // results depends on it.
+    pub const ORTHOGONAL: [Direction; 4] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Up,
+        Direction::Down,
+    ];
+
+    pub const ALL: [Direction; 8] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Up,
+        Direction::Down,
+        Direction::UpLeft,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::DownRight,
+    ];
+
+    // Change in `(x, y)` for one step.
+    pub fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (1, 1),
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::DownRight => Direction::UpLeft,
+        }
+    }
+}
+
+// Line and column numbers start at 1, unlike positions, since they point
+// into the text a grid was read from.
+#[derive(Debug, PartialEq)]
+pub enum GridError {
+    // A row with a different number of cells than the first one.
+    Ragged {
+        line: usize,
+        len: usize,
+        width: usize,
+    },
+    // A character the cell parser did not accept.
+    BadCell {
+        line: usize,
+        column: usize,
+        cell: char,
+    },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridError::Ragged { line, len, width } => {
+                write!(f, "line {}: {} cells, expected {}", line, len, width)
+            }
+            GridError::BadCell { line, column, cell } => {
+                write!(f, "line {}, column {}: unexpected {:?}", line, column, cell)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+// Rectangular grid stored row by row in a single `Vec`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    cells: Vec<T>,
+    width: usize,
+    height: usize,
+}
+
+impl<T> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Self
+    where
+        T: Clone,
+    {
+        Grid {
+            cells: vec![fill; width * height],
+            width,
+            height,
+        }
+    }
+
+    // All rows must be as long as the first one.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Self, GridError> {
+        let width = rows.first().map_or(0, Vec::len);
+        let height = rows.len();
+        let mut cells = Vec::with_capacity(width * height);
+        for (index, cells_in_row) in rows.into_iter().enumerate() {
+            if cells_in_row.len() != width {
+                return Err(GridError::Ragged {
+                    line: index + 1,
+                    len: cells_in_row.len(),
+                    width,
+                });
+            }
+            cells.extend(cells_in_row);
+        }
+
+        Ok(Grid {
+            cells,
+            width,
+            height,
+        })
+    }
+
+    // One row per line, one cell per character. `cell` returns `None` for
+    // characters that are not allowed.
+    pub fn parse(text: &str, cell: impl Fn(char) -> Option<T>) -> Result<Self, GridError> {
+        let rows = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(|(column, c)| {
+                        cell(c).ok_or(GridError::BadCell {
+                            line: index + 1,
+                            column: column + 1,
+                            cell: c,
+                        })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        Grid::from_rows(rows)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, pos: Pos) -> bool {
+        pos.x < self.width && pos.y < self.height
+    }
+
+    pub fn get(&self, pos: Pos) -> Option<&T> {
+        match self.contains(pos) {
+            true => Some(&self.cells[pos.y * self.width + pos.x]),
+            false => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, pos: Pos) -> Option<&mut T> {
+        match self.contains(pos) {
+            true => Some(&mut self.cells[pos.y * self.width + pos.x]),
+            false => None,
+        }
+    }
+
+    // All cells, row by row.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.cells.iter()
+    }
+
+    // All positions, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = Pos> {
+        let width = self.width;
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| Pos { x, y }))
+    }
+
+    pub fn row(&self, y: usize) -> &[T] {
+        assert!(y < self.height, "row {} outside {} rows", y, self.height);
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        assert!(y < self.height, "row {} outside {} rows", y, self.height);
+        &mut self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[T]> {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    pub fn column(&self, x: usize) -> impl DoubleEndedIterator<Item = &T> {
+        assert!(
+            x < self.width,
+            "column {} outside {} columns",
+            x,
+            self.width
+        );
+        (0..self.height).map(move |y| &self.cells[y * self.width + x])
+    }
+
+    // The neighbouring position in `direction`, if it is inside the grid.
+    pub fn step(&self, pos: Pos, direction: Direction) -> Option<Pos> {
+        let (dx, dy) = direction.delta();
+        let next = Pos {
+            x: pos.x.checked_add_signed(dx)?,
+            y: pos.y.checked_add_signed(dy)?,
+        };
+        match self.contains(next) {
+            true => Some(next),
+            false => None,
+        }
+    }
+
+    // The cells from `pos` (exclusive) to the edge of the grid in `direction`.
+    pub fn ray(&self, pos: Pos, direction: Direction) -> impl Iterator<Item = (Pos, &T)> {
+        std::iter::successors(self.step(pos, direction), move |&pos| {
+            self.step(pos, direction)
+        })
+        .map(|pos| (pos, &self[pos]))
+    }
+
+    // Neighbours sharing an edge, in the order of `Direction::ORTHOGONAL`.
+    pub fn neighbours4(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+        Direction::ORTHOGONAL
+            .into_iter()
+            .filter_map(move |direction| self.step(pos, direction))
+    }
+
+    // Neighbours sharing an edge or a corner, in the order of
+    // `Direction::ALL`.
+    pub fn neighbours8(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+        Direction::ALL
+            .into_iter()
+            .filter_map(move |direction| self.step(pos, direction))
+    }
+
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        Grid {
+            cells: self.cells.iter().map(f).collect(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl<T> Index<Pos> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, pos: Pos) -> &T {
+        let (width, height) = (self.width, self.height);
+        self.get(pos)
+            .unwrap_or_else(|| panic!("position {:?} outside {}x{} grid", pos, width, height))
+    }
+}
+
+impl<T> IndexMut<Pos> for Grid<T> {
+    fn index_mut(&mut self, pos: Pos) -> &mut T {
+        let (width, height) = (self.width, self.height);
+        self.get_mut(pos)
+            .unwrap_or_else(|| panic!("position {:?} outside {}x{} grid", pos, width, height))
+    }
+}
+
+// One line per row, cells written next to each other.
+impl<T: fmt::Display> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.rows() {
+            for cell in row {
+                write!(f, "{}", cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}